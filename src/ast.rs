@@ -0,0 +1,317 @@
+use std::fmt;
+
+use crate::util::{BaseType, Span, TokenType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp{
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    POW,
+    AND,
+    OR,
+    EQUAL,
+    NOTEQUAL,
+    GREATER,
+    LESS,
+    GOE,
+    LOE,
+    CAT
+}
+
+impl BinaryOp{
+    pub fn from_token(token_type: TokenType) -> Option<BinaryOp>{
+        match token_type {
+            TokenType::ADD => Some(BinaryOp::ADD),
+            TokenType::SUB => Some(BinaryOp::SUB),
+            TokenType::MUL => Some(BinaryOp::MUL),
+            TokenType::DIV => Some(BinaryOp::DIV),
+            TokenType::MOD => Some(BinaryOp::MOD),
+            TokenType::POW => Some(BinaryOp::POW),
+            TokenType::AND => Some(BinaryOp::AND),
+            TokenType::OR => Some(BinaryOp::OR),
+            TokenType::EQUAL => Some(BinaryOp::EQUAL),
+            TokenType::NOTEQUAL => Some(BinaryOp::NOTEQUAL),
+            TokenType::GREATER => Some(BinaryOp::GREATER),
+            TokenType::LESS => Some(BinaryOp::LESS),
+            TokenType::GOE => Some(BinaryOp::GOE),
+            TokenType::LOE => Some(BinaryOp::LOE),
+            TokenType::CAT => Some(BinaryOp::CAT),
+            _ => None,
+        }
+    }
+
+    /// the type both sides need, None when either type works as long as the sides agree
+    pub fn operand_type(&self) -> Option<BaseType>{
+        match self {
+            BinaryOp::CAT => Some(BaseType::STRING),
+            BinaryOp::EQUAL | BinaryOp::NOTEQUAL | BinaryOp::GREATER | BinaryOp::LESS | BinaryOp::GOE | BinaryOp::LOE => None,
+            _ => Some(BaseType::NUMBER),
+        }
+    }
+
+    /// | joins strings, everything else gives a number
+    pub fn result_type(&self) -> BaseType{
+        match self {
+            BinaryOp::CAT => BaseType::STRING,
+            _ => BaseType::NUMBER,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::ADD => "+",
+            BinaryOp::SUB => "-",
+            BinaryOp::MUL => "*",
+            BinaryOp::DIV => "/",
+            BinaryOp::MOD => "%",
+            BinaryOp::POW => "^",
+            BinaryOp::AND => "&&",
+            BinaryOp::OR => "||",
+            BinaryOp::EQUAL => "==",
+            BinaryOp::NOTEQUAL => "!=",
+            BinaryOp::GREATER => ">",
+            BinaryOp::LESS => "<",
+            BinaryOp::GOE => ">=",
+            BinaryOp::LOE => "<=",
+            BinaryOp::CAT => "|",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp{
+    NOT,
+    NEG
+}
+
+impl UnaryOp{
+    pub fn from_token(token_type: TokenType) -> Option<UnaryOp>{
+        match token_type {
+            TokenType::NOT => Some(UnaryOp::NOT),
+            TokenType::SUB => Some(UnaryOp::NEG),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOp::NOT => write!(f, "!"),
+            UnaryOp::NEG => write!(f, "-"),
+        }
+    }
+}
+
+/// CALL name(args), both a statement and an expression
+#[derive(Debug, Clone)]
+pub struct Call{
+    pub name: String,
+    pub args: Vec<Expr>,
+    //what the function is declared to return
+    pub ret_type: BaseType,
+    pub span: Span
+}
+
+/// the functions that come with the language, called without CALL
+/// LEN works on a LIST, MAP or STRING, PUSH and POP on a LIST, the rest on a MAP
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin{
+    //LEN(list) or LEN(string), how many items or chars
+    LEN,
+    //PUSH(list, item), adds item at the end
+    PUSH,
+    //POP(list), takes the last item off and gives it back
+    POP,
+    //HAS(map, key), 1 if the key is in the map
+    HAS,
+    //KEYS(map), a LIST<STRING> of the keys in sorted order
+    KEYS,
+    //DELETE(map, key), takes the key out
+    DELETE
+}
+
+impl Builtin{
+    pub fn from_name(name: &str) -> Option<Builtin>{
+        match name {
+            "LEN" => Some(Builtin::LEN),
+            "PUSH" => Some(Builtin::PUSH),
+            "POP" => Some(Builtin::POP),
+            "HAS" => Some(Builtin::HAS),
+            "KEYS" => Some(Builtin::KEYS),
+            "DELETE" => Some(Builtin::DELETE),
+            _ => None,
+        }
+    }
+
+    pub fn arity(&self) -> usize{
+        match self {
+            Builtin::PUSH | Builtin::HAS | Builtin::DELETE => 2,
+            Builtin::LEN | Builtin::POP | Builtin::KEYS => 1,
+        }
+    }
+
+    /// the ones that change their first argument where it is kept, so it has to be a variable
+    pub fn changes_arg(&self) -> bool{
+        matches!(self, Builtin::PUSH | Builtin::POP | Builtin::DELETE)
+    }
+}
+
+/// LEN(A), PUSH(A, 1) or POP(A), both a statement and an expression like Call
+#[derive(Debug, Clone)]
+pub struct BuiltinCall{
+    pub builtin: Builtin,
+    pub args: Vec<Expr>,
+    pub ret_type: BaseType,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub struct Param{
+    pub name: String,
+    pub val_type: BaseType,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub struct Function{
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret_type: BaseType,
+    pub body: Vec<Stmt>,
+    //FUNCTION up to the return type, what notes about the function point at
+    pub signature: Span,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr{
+    NUMBER{ val: f64, span: Span },
+    STRING{ val: String, span: Span },
+    //val_type is the declared type of the variable
+    VAR{ name: String, val_type: BaseType, span: Span },
+    CALL(Call),
+    BUILTIN(BuiltinCall),
+    //[a, b, c]
+    LIST{ items: Vec<Expr>, span: Span },
+    //{"key": value, ...}
+    MAP{ entries: Vec<(Expr, Expr)>, span: Span },
+    //list[index] or map[key], val_type is the type of the items of list
+    INDEX{ list: Box<Expr>, index: Box<Expr>, val_type: BaseType, span: Span },
+    UNARY{ op: UnaryOp, operand: Box<Expr>, span: Span },
+    BINARY{ op: BinaryOp, l: Box<Expr>, r: Box<Expr>, span: Span }
+}
+
+impl Expr{
+    pub fn span(&self) -> Span{
+        match self {
+            Expr::NUMBER { span, .. } | Expr::STRING { span, .. } | Expr::VAR { span, .. } => *span,
+            Expr::UNARY { span, .. } | Expr::BINARY { span, .. } => *span,
+            Expr::LIST { span, .. } | Expr::MAP { span, .. } | Expr::INDEX { span, .. } => *span,
+            Expr::CALL(call) => call.span,
+            Expr::BUILTIN(call) => call.span,
+        }
+    }
+
+    /// the same expression over another span, so (1 + 2) covers its brackets
+    pub fn with_span(self, span: Span) -> Expr{
+        match self {
+            Expr::NUMBER { val, .. } => Expr::NUMBER { val, span },
+            Expr::STRING { val, .. } => Expr::STRING { val, span },
+            Expr::VAR { name, val_type, .. } => Expr::VAR { name, val_type, span },
+            Expr::CALL(call) => Expr::CALL(Call { span, ..call }),
+            Expr::BUILTIN(call) => Expr::BUILTIN(BuiltinCall { span, ..call }),
+            Expr::LIST { items, .. } => Expr::LIST { items, span },
+            Expr::MAP { entries, .. } => Expr::MAP { entries, span },
+            Expr::INDEX { list, index, val_type, .. } => Expr::INDEX { list, index, val_type, span },
+            Expr::UNARY { op, operand, .. } => Expr::UNARY { op, operand, span },
+            Expr::BINARY { op, l, r, .. } => Expr::BINARY { op, l, r, span },
+        }
+    }
+
+    /// a variable or an item of one, what PUSH, POP, DELETE and list[index] = can change
+    pub fn is_place(&self) -> bool{
+        match self {
+            Expr::VAR { .. } => true,
+            Expr::INDEX { list, .. } => list.is_place(),
+            _ => false,
+        }
+    }
+
+    /// the type the parser gave it, the type checker makes sure the operands agree
+    pub fn val_type(&self) -> BaseType{
+        match self {
+            Expr::NUMBER { .. } | Expr::UNARY { .. } => BaseType::NUMBER,
+            Expr::STRING { .. } => BaseType::STRING,
+            Expr::VAR { val_type, .. } => val_type.clone(),
+            Expr::CALL(call) => call.ret_type.clone(),
+            Expr::BUILTIN(call) => call.ret_type.clone(),
+            //[] has no item to tell, LIST<VOID> fits any list
+            Expr::LIST { items, .. } => BaseType::LIST(Box::new(BaseType::of_items(items.iter().map(|item| item.val_type())))),
+            //{} is a MAP<STRING, VOID> the same way
+            Expr::MAP { entries, .. } => BaseType::MAP(Box::new(BaseType::of_items(entries.iter().map(|(_, val)| val.val_type())))),
+            Expr::INDEX { val_type, .. } => val_type.clone(),
+            Expr::BINARY { op, .. } => op.result_type(),
+        }
+    }
+}
+
+/// IF cond { body }, one link of an IF ... ELSE IF ... chain
+#[derive(Debug, Clone)]
+pub struct Branch{
+    pub cond: Expr,
+    pub body: Vec<Stmt>,
+    pub span: Span
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt{
+    //name: TYPE = value;
+    DECLARE{ name: String, val_type: BaseType, value: Expr, span: Span },
+    //name = value;
+    CHANGE{ name: String, value: Expr, span: Span },
+    PUT{ value: Expr, span: Span },
+    //the first branch whose cond holds runs, else_block runs when none does
+    IF{ branches: Vec<Branch>, else_block: Vec<Stmt>, span: Span },
+    //LABEL: WHILE cond { ... }; names the loop for BREAK LABEL; and CONTINUE LABEL;
+    WHILE{ cond: Expr, body: Vec<Stmt>, label: Option<String>, span: Span },
+    //FOR var: NUMBER = from TO to STEP step { ... }; var only lives in the loop, step is 1 when left out
+    FOR{ var: String, var_span: Span, from: Box<Expr>, to: Box<Expr>, step: Option<Box<Expr>>, body: Vec<Stmt>, label: Option<String>, span: Span },
+    //without a label they leave or go on with the innermost loop
+    BREAK{ label: Option<String>, span: Span },
+    CONTINUE{ label: Option<String>, span: Span },
+    FUNCTION(Function),
+    CALL(Call),
+    BUILTIN(BuiltinCall),
+    //list[index] = value; or map[key] = value; the list can be indexed itself, A[0][1] = 2;
+    SET{ target: Expr, value: Expr, span: Span },
+    RET{ value: Option<Expr>, span: Span },
+    //a lone ;
+    EMPTY{ span: Span },
+    EOF
+}
+
+impl Stmt{
+    pub fn span(&self) -> Span{
+        match self {
+            Stmt::DECLARE { span, .. } | Stmt::CHANGE { span, .. } | Stmt::PUT { span, .. } => *span,
+            Stmt::IF { span, .. } | Stmt::WHILE { span, .. } | Stmt::RET { span, .. } | Stmt::EMPTY { span } => *span,
+            Stmt::FOR { span, .. } | Stmt::BREAK { span, .. } | Stmt::CONTINUE { span, .. } => *span,
+            Stmt::FUNCTION(func) => func.span,
+            Stmt::CALL(call) => call.span,
+            Stmt::BUILTIN(call) => call.span,
+            Stmt::SET { span, .. } => *span,
+            Stmt::EOF => Span::default(),
+        }
+    }
+
+    pub fn is_eof(&self) -> bool{
+        matches!(self, Stmt::EOF)
+    }
+}
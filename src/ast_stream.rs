@@ -1,289 +1,691 @@
 
 use std::{ collections::HashMap, iter};
 
-use crate::{util::{NodeType, Stream, TokenType, BaseType}, token_stream::TokenStream};
-
-#[derive(Debug)]
-pub struct Node{
-    pub node_type: NodeType,
-    pub identity: String,
-    pub val_type: BaseType,
-    pub str_val: String,
-    pub num_val: f64,
-    pub l_children: Vec<Node>,
-    pub r_children: Vec<Node>,
-    pub extra: Vec<Node>
+use crate::{ast::{BinaryOp, Branch, Builtin, BuiltinCall, Call, Expr, Function, Param, Stmt, UnaryOp}, diagnostic::Diagnostic, util::{BaseType, Prelude, Span, Stream, TokenType}, token_stream::{number_value, Token, TokenStream}};
+
+//how deep blocks and the expressions in them can nest together, far more than any hand written program needs
+//each level takes a few KB of rust stack in a debug build, this fits in the 2MB a spawned thread gets
+const MAX_NESTING: usize = 128;
+
+//var register
+pub struct ASTStream{
+    pub in_stream: TokenStream,
+    cur: Stmt,
+    next: Stmt,
+    //one map per open block, innermost last, name -> (type, where it is declared)
+    //the outermost one starts with the prelude, which has no span
+    registered_var: Vec<HashMap<String, (BaseType, Option<Span>)>>,
+    //declarations whose block has ended, to explain why a name is not found
+    out_of_scope: HashMap<String, Span>,
+    //name -> (param types, return type, where the signature is)
+    registered_func: HashMap<String, (Vec<BaseType>, BaseType, Option<Span>)>,
+    //return type of the function being parsed, None at the top level
+    ret_type: Option<BaseType>,
+    //the label of every loop around what is being parsed, innermost last
+    loops: Vec<Option<String>>,
+    //how deep the expression being parsed is, see MAX_NESTING
+    depth: usize
 }
 
-impl Clone for Node{
-    fn clone(&self) -> Node {
-        Node { node_type: self.node_type.clone(), identity: self.identity.clone(), val_type: self.val_type.clone(), str_val: self.str_val.clone(), num_val: self.num_val.clone(), l_children: self.l_children.clone(), r_children: self.r_children.clone(), extra: self.extra.clone() }
+
+impl ASTStream{
+    /// cur points to the last one that matched     
+    /// parse next move to a new line start
+    /// parse others point to the first one that matches
+    pub fn new(in_stream: TokenStream, prelude: &Prelude) -> Result<ASTStream, Vec<Diagnostic>>{
+        let mut stream = ASTStream::unprimed(in_stream, prelude);
+        match stream.parse_next() {
+            Ok(stmt) => stream.next = stmt,
+            Err(e) => return Err(stream.with_lex_errors(*e)),
+        }
+        Ok(stream)
     }
-}
 
-impl Node{
-    pub fn new(node_type: NodeType, id: String) -> Node{
-        Node { node_type, identity: id, val_type: BaseType::VOID, str_val: String::from(""), num_val: 0.0, l_children: vec![], r_children: vec![], extra: vec![Node::new_eof()] }
+    fn unprimed(in_stream: TokenStream, prelude: &Prelude) -> ASTStream{
+        let globals = prelude.vars.iter().map(|(name, var_type)| (name.clone(), (var_type.clone(), None))).collect();
+        let funcs = prelude.funcs.iter().map(|(name, (params, ret))| (name.clone(), (params.clone(), ret.clone(), None))).collect();
+        ASTStream { in_stream, cur: Stmt::EOF, next: Stmt::EOF, registered_var: vec![globals], out_of_scope: HashMap::new(), registered_func: funcs, ret_type: None, loops: vec![], depth: 0 }
     }
-    pub fn new_eof() -> Node{
-        Node { node_type: NodeType::EOF, identity: String::from(""), val_type: BaseType::VOID, str_val: String::from(""), num_val: 0.0, l_children: vec![], r_children: vec![], extra: vec![] }
+
+    /// the whole of in_stream as one expression, a ; after it is allowed
+    pub fn parse_lone_expression(in_stream: TokenStream, prelude: &Prelude) -> Result<Expr, Vec<Diagnostic>>{
+        let mut stream = ASTStream::unprimed(in_stream, prelude);
+        stream.lone_expression().map_err(|e| stream.with_lex_errors(*e))
     }
-    pub fn append_l(&mut self, node: Node){
-        if self.l_children.len() == 1 && self.l_children[0].node_type.is_eof() {
-            self.l_children.pop();
+
+    fn lone_expression(&mut self) -> Result<Expr, Box<Diagnostic>>{
+        self.in_stream.next()?;
+        let expr = self.parse_expression()?;
+        if let TokenType::SEMICOLON = self.in_stream.peek().token_type {
+            self.in_stream.next()?;
         }
-        self.l_children.push(node);
+        if self.in_stream.peek().token_type != TokenType::EOF {
+            self.in_stream.next()?;
+            return Err(self.err("E0201", "there should be nothing after the expression").into());
+        }
+        Ok(expr)
     }
-    pub fn append_e(&mut self, node: Node){
-        if self.extra.len() == 1 && self.extra[0].node_type.is_eof() {
-            self.extra.pop();
+
+    /// e and every unknown char in the source, e is left out when it only complains about one of them
+    pub fn with_lex_errors(&mut self, e: Diagnostic) -> Vec<Diagnostic>{
+        let on_error_token = self.in_stream.current().token_type == TokenType::ERROR && !e.code.starts_with("E01");
+        let mut errors = self.in_stream.drain_errors();
+        if !on_error_token || errors.is_empty() {
+            errors.push(e);
         }
-        self.extra.push(node);
+        errors.sort_by_key(|diagnostic| diagnostic.span.map(|span| span.start));
+        errors
     }
-    pub fn append_r(&mut self, node: Node){
-        if self.r_children.len() == 1 && self.r_children[0].node_type.is_eof() {
-            self.r_children.pop();
+
+    /// a name can be declared once per block, an inner block may shadow it
+    fn register_var(&mut self, var_type: BaseType, var_name: String, span: Span) -> Result<(), Box<Diagnostic>>{
+        let scope = self.registered_var.last_mut().unwrap();
+        if let Some((_, first)) = scope.get(&var_name) {
+            return Err(Diagnostic::error("E0209", format!("{} is already declared in this block", var_name), Some(span))
+                .with_note(format!("{} is first declared here", var_name), *first)
+                .with_help(String::from("assign to it with = instead, or declare it in an inner block to shadow it")).into());
         }
-        self.r_children.push(node);
+        scope.extend(iter::once((var_name, (var_type, Some(span)))));
+        Ok(())
     }
-}
-//var register
-pub struct ASTStream{
-    pub in_stream: TokenStream,
-    cur: Node,
-    next: Node,
-    registered_var: HashMap<String, BaseType>
-}
 
+    /// innermost declaration first
+    fn lookup_var(&self, name: &str) -> Option<BaseType>{
+        self.registered_var.iter().rev().find_map(|scope| scope.get(name)).map(|(var_type, _)| var_type.clone())
+    }
 
-impl ASTStream{
-    /// cur points to the last one that matched     
-    /// parse next move to a new line start
-    /// parse others point to the first one that matches
-    pub fn new(path: &str) -> ASTStream{
-        let mut stream = ASTStream { in_stream: TokenStream::new(path), cur: Node::new_eof(), next: Node::new_eof(), registered_var: HashMap::new() };
-        stream.next = stream.parse_next();
-        stream
+    fn open_scope(&mut self){
+        self.registered_var.push(HashMap::new());
     }
 
-    fn register_var(&mut self, var_type: BaseType, var_name: String){
-        self.registered_var.extend(iter::once((var_name, var_type)));
+    fn close_scope(&mut self){
+        if let Some(scope) = self.registered_var.pop() {
+            for (name, (_, span)) in scope {
+                if let Some(span) = span {
+                    self.out_of_scope.insert(name, span);
+                }
+            }
+        }
     }
 
-    fn parse_val(&mut self) -> Node{
-        let mut node = Node::new(NodeType::VAL, String::from(""));
-        match self.in_stream.current().token_type {
+    /// from start to the end of cur
+    fn span_from(&self, start: Span) -> Span{
+        start.to(self.in_stream.current().span)
+    }
+
+    fn undeclared(&self, name: &str) -> Diagnostic{
+        let diagnostic = self.err("E0202", &format!("variable {} is not declared", name));
+        match self.out_of_scope.get(name) {
+            Some(span) => diagnostic.with_note(format!("{} is declared here, but only lives until the end of that block", name), Some(*span)),
+            None => diagnostic.with_help(format!("declare it before using it, like `{}: NUMBER = 0;`", name)),
+        }
+    }
+
+    /// moves to the next token and checks it is the one the grammar wants here
+    fn expect(&mut self, token_type: TokenType, msg: &str) -> Result<Token, Box<Diagnostic>>{
+        let token = self.in_stream.next()?;
+        if token.token_type != token_type {
+            return Err(self.err("E0201", msg).into());
+        }
+        Ok(token)
+    }
+
+    /// { stmt* }, cur points to the token before the { and to the } when it returns
+    /// declarations inside are dropped at the }
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, Box<Diagnostic>>{
+        self.expect(TokenType::BEGIN, "there should be a { here")?;
+        if self.depth + self.registered_var.len() > MAX_NESTING {
+            return Err(self.err("E0201", "blocks are nested too deep").into());
+        }
+        self.open_scope();
+        let stmts = self.parse_block_body();
+        self.close_scope();
+        stmts
+    }
+
+    fn parse_block_body(&mut self) -> Result<Vec<Stmt>, Box<Diagnostic>>{
+        let mut stmts = vec![];
+        loop {
+            match self.in_stream.peek().token_type {
+                TokenType::END => {
+                    break;
+                }
+                TokenType::EOF => {
+                    return Err(self.err("E0208", "unclosed {, there should be a } before the end of file").into());
+                }
+                _ => {
+                    match self.parse_next()? {
+                        Stmt::EMPTY { .. } => {}
+                        stmt => stmts.push(stmt),
+                    }
+                }
+            }
+        }
+        self.in_stream.next()?;
+        Ok(stmts)
+    }
+
+    fn parse_val(&mut self) -> Result<Expr, Box<Diagnostic>>{
+        let token = self.in_stream.current();
+        match token.token_type {
             TokenType::STRING => {
-                node.val_type = BaseType::STRING;
-                node.str_val = self.in_stream.current().value;
+                Ok(Expr::STRING { val: token.value, span: token.span })
             }
             TokenType::NUMBER => {
-                node.val_type = BaseType::NUMBER;
-                node.num_val = self.in_stream.current().value.parse::<f64>().unwrap();
+                match number_value(&token.value) {
+                    Ok(val) => Ok(Expr::NUMBER { val, span: token.span }),
+                    Err(_) => Err(self.err("E0207", &format!("malformed number {}", token.value)).into()),
+                }
             }
             _ => {
-                self.err("prase val type err");
+                Err(self.err("E0201", "prase val type err").into())
             }
         }
-        node
     }
 
-    fn parse_expression(&mut self) -> Node{
-        let mut node = Node::new(NodeType::EXPRESSION, String::from(""));
+    /// precedence climbing over TokenType::precedence
+    /// cur points to the first token of the expression, and to the last one when it returns
+    fn parse_expression(&mut self) -> Result<Expr, Box<Diagnostic>>{
+        self.parse_binary(0)
+    }
+
+    /// every operator, bracket and index is one level deeper, the levels are counted so generated input cannot overflow the stack
+    fn parse_binary(&mut self, min_prec: u8) -> Result<Expr, Box<Diagnostic>>{
+        let outer = self.depth;
+        let expr = self.deeper().and_then(|_| self.climb(min_prec));
+        self.depth = outer;
+        expr
+    }
+
+    fn deeper(&mut self) -> Result<(), Box<Diagnostic>>{
+        //the blocks around it count too
+        if self.depth + self.registered_var.len() > MAX_NESTING {
+            return Err(self.err("E0201", "the expression is nested too deep").into());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn climb(&mut self, min_prec: u8) -> Result<Expr, Box<Diagnostic>>{
+        let mut expr = self.parse_unary()?;
+        loop {
+            let token_type = self.in_stream.peek().token_type;
+            let (op, prec) = match (BinaryOp::from_token(token_type), token_type.precedence()) {
+                (Some(op), Some(prec)) if prec >= min_prec => (op, prec),
+                _ => break,
+            };
+            //A + B + C is (A + B) + C, the tree grows a level with every operator
+            self.deeper()?;
+            self.in_stream.next()?;
+            self.in_stream.next()?;
+            let next_min = if token_type.is_right_assoc() { prec } else { prec + 1 };
+            let r = self.parse_binary(next_min)?;
+            let span = expr.span().to(r.span());
+            expr = Expr::BINARY { op, l: Box::new(expr), r: Box::new(r), span };
+        }
+        Ok(expr)
+    }
+
+    /// prefix ! and -
+    fn parse_unary(&mut self) -> Result<Expr, Box<Diagnostic>>{
+        match UnaryOp::from_token(self.in_stream.current().token_type) {
+            Some(op) => {
+                let start = self.in_stream.current().span;
+                self.in_stream.next()?;
+                let operand = self.parse_binary(TokenType::unary_precedence())?;
+                let span = start.to(operand.span());
+                Ok(Expr::UNARY { op, operand: Box::new(operand), span })
+            }
+            None => {
+                self.parse_primary()
+            }
+        }
+    }
+
+    /// an atom and any [index] after it
+    fn parse_primary(&mut self) -> Result<Expr, Box<Diagnostic>>{
+        let mut expr = self.parse_atom()?;
+        while let TokenType::LSQUARE = self.in_stream.peek().token_type {
+            self.deeper()?;
+            self.in_stream.next()?;
+            self.in_stream.next()?;
+            let index = self.parse_expression()?;
+            let span = expr.span().to(self.expect(TokenType::RSQUARE, "missing ] after the index")?.span);
+            //the type checker complains when it is not a list or map
+            let val_type = match expr.val_type() {
+                BaseType::LIST(item) | BaseType::MAP(item) => *item,
+                _ => BaseType::VOID,
+            };
+            expr = Expr::INDEX { list: Box::new(expr), index: Box::new(index), val_type, span };
+        }
+        Ok(expr)
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr, Box<Diagnostic>>{
         match self.in_stream.current().token_type {
-            TokenType::STRING => {
-                node.val_type = BaseType::STRING;
-                match self.in_stream.peek().token_type {
-                    TokenType::CAT => {
-                        node.str_val = String::from("|");
-                        node.append_l(self.parse_val());
-                        self.in_stream.next();
-                        self.in_stream.next();
-                        node.append_r(self.parse_expression());
-                    }
-                    _ => {
-                        node = self.parse_val();
-                    }
-                }
+            TokenType::STRING | TokenType::NUMBER => {
+                self.parse_val()
             }
-            TokenType::LBRAC => {
-                self.in_stream.next();
-                let tmp = self.parse_expression();
-                self.in_stream.next();
-                if self.in_stream.peek().token_type.is_num_op() || self.in_stream.peek().token_type.is_cat() {
-                    if self.in_stream.peek().token_type.is_num_op() {
-                        node.val_type = BaseType::NUMBER;
-                    }
-                    else if self.in_stream.peek().token_type.is_cat() {
-                        node.val_type = BaseType::STRING;
-                    }
-                    node.append_l(tmp);
-                    node.str_val = self.in_stream.next().value;
-                    self.in_stream.next();
-                    node.append_r(self.parse_expression());
-                }
-                else {
-                    node = tmp;
+            TokenType::VAR if self.in_stream.peek().token_type == TokenType::LBRAC => {
+                let token = self.in_stream.current();
+                match Builtin::from_name(&token.value) {
+                    Some(builtin) => Ok(Expr::BUILTIN(self.parse_builtin(builtin)?)),
+                    None => Err(self.err("E0203", &format!("{} is not a builtin, a function is called with CALL {}(...)", token.value, token.value)).into()),
                 }
             }
+            TokenType::LSQUARE => {
+                let start = self.in_stream.current().span;
+                let items = self.parse_items(TokenType::RSQUARE, "there should be a , or ] after a list item")?;
+                Ok(Expr::LIST { items, span: self.span_from(start) })
+            }
+            TokenType::BEGIN => {
+                self.parse_map()
+            }
             TokenType::VAR => {
-                let mut tmp = Node::new(NodeType::VAL, self.in_stream.current().value);
-                tmp.val_type = self.registered_var[&tmp.identity].clone();
-                if self.in_stream.peek().token_type.is_num_op() ||  self.in_stream.peek().token_type.is_cat() {
-                    node.val_type = self.registered_var[&tmp.identity].clone();
-                    node.str_val = self.in_stream.peek().value;
-                    node.append_l(tmp);
-                    self.in_stream.next();
-                    self.in_stream.next();
-                    node.append_r(self.parse_expression());
-                }
-                else{
-                    node = tmp;
+                let token = self.in_stream.current();
+                match self.lookup_var(&token.value) {
+                    Some(val_type) => Ok(Expr::VAR { name: token.value, val_type, span: token.span }),
+                    None => Err(self.undeclared(&token.value).into()),
                 }
             }
-            TokenType::NOT => {
-                node.val_type = BaseType::NUMBER;
-                node.str_val = String::from("!");
-                self.in_stream.next();
-                node.append_r(self.parse_expression());
+            TokenType::CALL => {
+                Ok(Expr::CALL(self.parse_call()?))
             }
-            TokenType::NUMBER =>{
-                node.val_type = BaseType::NUMBER;
-                let next_token = self.in_stream.peek();
-                if next_token.token_type.is_num_op() {
-                    node.str_val = next_token.value;
-                    node.append_l(self.parse_val());
-                    self.in_stream.next();
-                    self.in_stream.next();
-                    node.append_r(self.parse_expression());
-                }
-                else {
-                    node = self.parse_val();
-                }
+            TokenType::LBRAC => {
+                //the brackets are not kept, the span of what is inside grows to cover them
+                let start = self.in_stream.current().span;
+                self.in_stream.next()?;
+                let expr = self.parse_expression()?;
+                let span = start.to(self.expect(TokenType::RBRAC, "missing ) in expression")?.span);
+                Ok(expr.with_span(span))
             }
             _ => {
-                self.err("Illegal ch in expression");
+                Err(self.err("E0201", "Illegal ch in expression").into())
             }
         }
-        node
     }
 
-    fn parse_declare(&mut self) -> Node {
-        let name = self.in_stream.current().value;
-        let mut node = Node::new(NodeType::DECLARE, self.in_stream.current().value);
-        //skip :
-        self.in_stream.next();
-        let type_token = self.in_stream.next();
-        //now cur points to the type token
-        match type_token.token_type {
-            TokenType::TYPESTRING => {
-                node.val_type = BaseType::STRING;
-                self.register_var(BaseType::STRING, name);
+    /// cur points to the type token
+    fn parse_type(&mut self) -> Result<BaseType, Box<Diagnostic>> {
+        match self.in_stream.current().token_type {
+            TokenType::TYPESTRING => Ok(BaseType::STRING),
+            TokenType::TYPENUMBER => Ok(BaseType::NUMBER),
+            //LIST<type>, the item type can be a LIST itself
+            TokenType::TYPELIST => {
+                self.expect(TokenType::LESS, "there should be a < after LIST")?;
+                self.in_stream.next()?;
+                let item = self.parse_type()?;
+                self.expect(TokenType::GREATER, "there should be a > after the item type")?;
+                Ok(BaseType::LIST(Box::new(item)))
             }
-            TokenType::TYPENUMBER => {
-                node.val_type = BaseType::NUMBER;
-                self.register_var(BaseType::NUMBER, name);
+            //MAP<STRING, type>, only STRING keys for now
+            TokenType::TYPEMAP => {
+                self.expect(TokenType::LESS, "there should be a < after MAP")?;
+                self.expect(TokenType::TYPESTRING, "the keys of a MAP should be STRING")?;
+                self.expect(TokenType::COMMA, "there should be a , after the key type")?;
+                self.in_stream.next()?;
+                let item = self.parse_type()?;
+                self.expect(TokenType::GREATER, "there should be a > after the value type")?;
+                Ok(BaseType::MAP(Box::new(item)))
             }
-            _ => {
-                self.err("WRONG parsing TYPE");
+            _ => Err(self.err("E0201", "WRONG parsing TYPE").into()),
+        }
+    }
+
+    fn parse_declare(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let name = self.in_stream.current();
+        //skip :
+        self.in_stream.next()?;
+        //LABEL: WHILE ... or LABEL: FOR ... names the loop instead
+        match self.in_stream.peek().token_type {
+            TokenType::WHILE => {
+                self.in_stream.next()?;
+                return self.parse_while(Some(name));
             }
+            TokenType::FOR => {
+                self.in_stream.next()?;
+                return self.parse_for(Some(name));
+            }
+            _ => {}
         }
+        self.in_stream.next()?;
+        //now cur points to the type token
+        let val_type = self.parse_type()?;
         //skip =
-        self.in_stream.next();
+        self.expect(TokenType::ASSIGN, "there should be a = after the type")?;
         //now cur points to the =
-        self.in_stream.next();
-        node.append_l(self.parse_expression());
-        node
+        self.in_stream.next()?;
+        let value = self.parse_expression()?;
+        let span = self.span_from(name.span);
+        //registered after the value, so X: NUMBER = X + 1; in a block reads the outer X
+        self.register_var(val_type.clone(), name.value.clone(), span)?;
+        Ok(Stmt::DECLARE { name: name.value, val_type, value, span })
     }
 
-    fn parse_put(&mut self) -> Node{
-        let mut node = Node::new(NodeType::PUT, String::from(""));
-        self.in_stream.next();
-        node.append_l(self.parse_expression());
-        node
+    /// FUNCTION name(a: NUMBER, b: STRING): NUMBER { ... };
+    fn parse_function(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        //inside any block, a function body could see locals the running CALL has no way to reach
+        if self.registered_var.len() > 1 {
+            return Err(self.err("E0206", "FUNCTION can only be declared at the top level").into());
+        }
+        let name = self.expect(TokenType::VAR, "there should be a name after FUNCTION")?.value;
+        //a CALL already parsed against the first signature would run the second one
+        if let Some((_, _, first)) = self.registered_func.get(&name) {
+            return Err(self.err("E0212", &format!("function {} is already declared", name))
+                .with_note(format!("{} is first declared here", name), *first)
+                .with_help(String::from("give the new function a name of its own")).into());
+        }
+        self.expect(TokenType::LBRAC, "there should be a ( after the function name")?;
+        let mut params = vec![];
+        //every param is followed by a , or the )
+        if self.in_stream.peek().token_type == TokenType::RBRAC {
+            self.in_stream.next()?;
+        }
+        else {
+            loop {
+                let name = self.expect(TokenType::VAR, "there should be a param name here")?;
+                self.expect(TokenType::COLON, "there should be a : after the param name")?;
+                self.in_stream.next()?;
+                let val_type = self.parse_type()?;
+                params.push(Param { name: name.value, val_type, span: self.span_from(name.span) });
+                match self.in_stream.next()?.token_type {
+                    TokenType::COMMA => {}
+                    TokenType::RBRAC => break,
+                    _ => return Err(self.err("E0201", "there should be a , or ) after a param").into()),
+                }
+            }
+        }
+        //now cur points to the )
+        let mut ret_type = BaseType::VOID;
+        if let TokenType::COLON = self.in_stream.peek().token_type {
+            self.in_stream.next()?;
+            self.in_stream.next()?;
+            ret_type = self.parse_type()?;
+        }
+        //registered before the body so it can call itself
+        let signature = self.span_from(start);
+        let param_types = params.iter().map(|param| param.val_type.clone()).collect();
+        self.registered_func.insert(name.clone(), (param_types, ret_type.clone(), Some(signature)));
+        //params live in a scope of their own around the body
+        self.open_scope();
+        self.ret_type = Some(ret_type.clone());
+        let body = params.clone().into_iter()
+            .try_for_each(|param| self.register_var(param.val_type, param.name, param.span))
+            .and_then(|_| self.parse_block());
+        self.ret_type = None;
+        self.close_scope();
+        let body = body?;
+        self.expect(TokenType::SEMICOLON, "there should be a ; after }")?;
+        Ok(Stmt::FUNCTION(Function { name, params, ret_type, body, signature, span: self.span_from(start) }))
     }
 
-    fn parse_if(&mut self) -> Node {
-        let mut node = Node::new(NodeType::IF, String::from(""));
-        self.in_stream.next();
-        node.append_e(self.parse_expression());
-        self.in_stream.next();
-        loop{
-            match self.in_stream.peek().token_type {
-                TokenType::END => {
-                    break;
+    /// expressions split by , up to close, cur points to the token that opens them and to close when it returns
+    fn parse_items(&mut self, close: TokenType, msg: &str) -> Result<Vec<Expr>, Box<Diagnostic>> {
+        let mut items = vec![];
+        if self.in_stream.peek().token_type == close {
+            self.in_stream.next()?;
+            return Ok(items);
+        }
+        loop {
+            self.in_stream.next()?;
+            items.push(self.parse_expression()?);
+            match self.in_stream.next()?.token_type {
+                TokenType::COMMA => {}
+                token_type if token_type == close => {
+                    return Ok(items);
                 }
                 _ => {
-                    node.append_l(self.parse_next());
+                    return Err(self.err("E0201", msg).into());
                 }
             }
         }
-        self.in_stream.next();
-        self.in_stream.next();
-        match self.in_stream.current().token_type {
-            TokenType::ELSE => {
-                self.in_stream.next();
-                loop {
-                    match self.in_stream.peek().token_type {
-                        TokenType::END => {
-                            break;
-                        }
-                        _ => {
-                            node.append_r(self.parse_next());
-                        }
+    }
+
+    /// LEN(A), PUSH(A, 1), HAS(M, "k") and the rest, cur is the name
+    fn parse_builtin(&mut self, builtin: Builtin) -> Result<BuiltinCall, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        let name = self.in_stream.current().value;
+        self.expect(TokenType::LBRAC, "there should be a ( after the function name")?;
+        let args = self.parse_items(TokenType::RBRAC, "there should be a , or ) after an argument")?;
+        let span = self.span_from(start);
+        if args.len() != builtin.arity() {
+            let msg = format!("{} takes {} arguments but was given {}", name, builtin.arity(), args.len());
+            return Err(Diagnostic::error("E0204", msg, Some(span)).into());
+        }
+        if builtin.changes_arg() && !args[0].is_place() {
+            return Err(Diagnostic::error("E0201", format!("the first argument of {} should be a variable", name), Some(args[0].span())).into());
+        }
+        let ret_type = match (builtin, args[0].val_type()) {
+            (Builtin::LEN | Builtin::HAS, _) => BaseType::NUMBER,
+            (Builtin::KEYS, _) => BaseType::LIST(Box::new(BaseType::STRING)),
+            (Builtin::POP, BaseType::LIST(item)) => *item,
+            _ => BaseType::VOID,
+        };
+        Ok(BuiltinCall { builtin, args, ret_type, span })
+    }
+
+    /// {"key": value, ...}, cur is the {
+    fn parse_map(&mut self) -> Result<Expr, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        let mut entries = vec![];
+        if let TokenType::END = self.in_stream.peek().token_type {
+            self.in_stream.next()?;
+        }
+        else {
+            loop {
+                self.in_stream.next()?;
+                let key = self.parse_expression()?;
+                self.expect(TokenType::COLON, "there should be a : after the key")?;
+                self.in_stream.next()?;
+                entries.push((key, self.parse_expression()?));
+                match self.in_stream.next()?.token_type {
+                    TokenType::COMMA => {}
+                    TokenType::END => {
+                        break;
+                    }
+                    _ => {
+                        return Err(self.err("E0201", "there should be a , or } after a map entry").into());
                     }
                 }
             }
-            TokenType::SEMICOLON => {
-                return node;
+        }
+        Ok(Expr::MAP { entries, span: self.span_from(start) })
+    }
+
+    /// list[index] = value; or map[key] = value; cur is the name
+    fn parse_set(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        let target = self.parse_primary()?;
+        self.expect(TokenType::ASSIGN, "there should be a = after the index")?;
+        self.in_stream.next()?;
+        let value = self.parse_expression()?;
+        Ok(Stmt::SET { target, value, span: self.span_from(start) })
+    }
+
+    /// CALL name(args), as a statement or inside an expression
+    fn parse_call(&mut self) -> Result<Call, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        let name = self.expect(TokenType::VAR, "there should be a function name after CALL")?.value;
+        let (params, ret_type, signature) = match self.registered_func.get(&name) {
+            Some(sig) => sig.clone(),
+            None => return Err(self.err("E0203", &format!("CALL of an undeclared function {}", name)).into()),
+        };
+        self.expect(TokenType::LBRAC, "there should be a ( after the function name")?;
+        let args = self.parse_items(TokenType::RBRAC, "there should be a , or ) after an argument")?;
+        let span = self.span_from(start);
+        if args.len() != params.len() {
+            let msg = format!("{} takes {} arguments but was given {}", name, params.len(), args.len());
+            return Err(Diagnostic::error("E0204", msg, Some(span))
+                .with_note(format!("{} is declared here", name), signature).into());
+        }
+        Ok(Call { name, args, ret_type, span })
+    }
+
+    /// RET expr; or RET; in a function without a return type
+    fn parse_ret(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        let ret_type = match &self.ret_type {
+            Some(ret_type) => ret_type.clone(),
+            None => {
+                return Err(self.err("E0205", "RET outside of a FUNCTION").into());
             }
-            _ => {
-                self.err("parse if, there should be a ; after }");
+        };
+        let mut value = None;
+        if self.in_stream.peek().token_type != TokenType::SEMICOLON {
+            self.in_stream.next()?;
+            value = Some(self.parse_expression()?);
+        }
+        match (&ret_type, &value) {
+            (BaseType::VOID, Some(_)) => {
+                return Err(self.err("E0205", "RET with a value in a function without a return type").into());
+            }
+            (ret_type, None) if *ret_type != BaseType::VOID => {
+                return Err(self.err("E0205", "RET without a value in a function with a return type").into());
             }
+            _ => {}
         }
-        self.in_stream.next();
-        node
+        Ok(Stmt::RET { value, span: self.span_from(start) })
     }
 
-    fn parse_change(&mut self) -> Node {
-        let mut node = Node::new(NodeType::CHANGE, self.in_stream.current().value);
-        self.in_stream.next();
-        self.in_stream.next();
-        node.append_l(self.parse_expression());
-        node
+    fn parse_put(&mut self) -> Result<Stmt, Box<Diagnostic>>{
+        let start = self.in_stream.current().span;
+        self.in_stream.next()?;
+        let value = self.parse_expression()?;
+        Ok(Stmt::PUT { value, span: self.span_from(start) })
     }
 
-    fn parse_while(&mut self) -> Node {
-        let mut node = Node::new(NodeType::WHILE, String::from(""));
-        self.in_stream.next();
-        node.append_e(self.parse_expression());
-        self.in_stream.next();
+    /// IF a { ... } ELSE IF b { ... } ELSE { ... }; as one flat chain
+    fn parse_if(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        let mut branches = vec![self.parse_branch()?];
+        let mut else_block = vec![];
         loop {
-            match self.in_stream.peek().token_type {
-                TokenType::END => {
+            self.in_stream.next()?;
+            match self.in_stream.current().token_type {
+                TokenType::ELSE if self.in_stream.peek().token_type == TokenType::IF => {
+                    self.in_stream.next()?;
+                    branches.push(self.parse_branch()?);
+                }
+                TokenType::ELSE => {
+                    else_block = self.parse_block()?;
+                    self.expect(TokenType::SEMICOLON, "parse if, there should be a ; after }")?;
+                    break;
+                }
+                TokenType::SEMICOLON => {
                     break;
                 }
                 _ => {
-                    node.append_l(self.parse_next());
+                    return Err(self.err("E0201", "parse if, there should be ELSE or a ; after }").into());
                 }
             }
         }
-        self.in_stream.next();
-        self.in_stream.next();
-        node
+        Ok(Stmt::IF { branches, else_block, span: self.span_from(start) })
     }
 
-    fn parse_next(&mut self) -> Node{
-        let cur_token = self.in_stream.next();
+    //cur is the IF
+    fn parse_branch(&mut self) -> Result<Branch, Box<Diagnostic>> {
+        let start = self.in_stream.current().span;
+        self.in_stream.next()?;
+        let cond = self.parse_expression()?;
+        let body = self.parse_block()?;
+        Ok(Branch { cond, body, span: self.span_from(start) })
+    }
+
+    fn parse_change(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let name = self.in_stream.current();
+        if self.lookup_var(&name.value).is_none() {
+            return Err(self.undeclared(&name.value).into());
+        }
+        self.in_stream.next()?;
+        self.in_stream.next()?;
+        let value = self.parse_expression()?;
+        Ok(Stmt::CHANGE { name: name.value, value, span: self.span_from(name.span) })
+    }
+
+    /// WHILE cond { ... }; cur is the WHILE, label is the name before it if there is one
+    fn parse_while(&mut self, label: Option<Token>) -> Result<Stmt, Box<Diagnostic>> {
+        let start = label.as_ref().map(|label| label.span).unwrap_or(self.in_stream.current().span);
+        let label = label.map(|label| label.value);
+        self.in_stream.next()?;
+        let cond = self.parse_expression()?;
+        self.loops.push(label.clone());
+        let body = self.parse_block();
+        self.loops.pop();
+        let body = body?;
+        self.expect(TokenType::SEMICOLON, "there should be a ; after }")?;
+        Ok(Stmt::WHILE { cond, body, label, span: self.span_from(start) })
+    }
+
+    /// FOR I: NUMBER = a TO b STEP c { ... }; cur is the FOR, I is declared in a scope around the body
+    fn parse_for(&mut self, label: Option<Token>) -> Result<Stmt, Box<Diagnostic>> {
+        let start = label.as_ref().map(|label| label.span).unwrap_or(self.in_stream.current().span);
+        let label = label.map(|label| label.value);
+        let var = self.expect(TokenType::VAR, "there should be a loop variable after FOR")?;
+        self.expect(TokenType::COLON, "there should be a : after the loop variable")?;
+        self.expect(TokenType::TYPENUMBER, "the loop variable of a FOR should be a NUMBER")?;
+        let var_span = self.span_from(var.span);
+        self.expect(TokenType::ASSIGN, "there should be a = after the type")?;
+        self.in_stream.next()?;
+        let from = self.parse_expression()?;
+        self.expect(TokenType::TO, "there should be a TO after the first value of a FOR")?;
+        self.in_stream.next()?;
+        let to = self.parse_expression()?;
+        let mut step = None;
+        if let TokenType::STEP = self.in_stream.peek().token_type {
+            self.in_stream.next()?;
+            self.in_stream.next()?;
+            step = Some(Box::new(self.parse_expression()?));
+        }
+        //registered after the bounds, so FOR I: NUMBER = I TO 3 reads an outer I
+        self.open_scope();
+        self.loops.push(label.clone());
+        let body = self.register_var(BaseType::NUMBER, var.value.clone(), var_span)
+            .and_then(|_| self.parse_block());
+        self.loops.pop();
+        self.close_scope();
+        let body = body?;
+        self.expect(TokenType::SEMICOLON, "there should be a ; after }")?;
+        Ok(Stmt::FOR { var: var.value, var_span, from: Box::new(from), to: Box::new(to), step, body, label, span: self.span_from(start) })
+    }
+
+    /// BREAK; or CONTINUE; with an optional loop label, only inside a WHILE or FOR
+    fn parse_jump(&mut self) -> Result<Stmt, Box<Diagnostic>> {
+        let keyword = self.in_stream.current();
+        if self.loops.is_empty() {
+            return Err(self.err("E0210", &format!("{} outside of a WHILE or FOR", keyword.value)).into());
+        }
+        let mut label = None;
+        if let TokenType::VAR = self.in_stream.peek().token_type {
+            let name = self.in_stream.next()?;
+            if !self.loops.contains(&Some(name.value.clone())) {
+                return Err(self.err("E0211", &format!("there is no loop labelled {} around this {}", name.value, keyword.value))
+                    .with_help(format!("label a loop by writing {}: before its WHILE or FOR", name.value)).into());
+            }
+            label = Some(name.value);
+        }
+        let span = self.span_from(keyword.span);
+        match keyword.token_type {
+            TokenType::BREAK => Ok(Stmt::BREAK { label, span }),
+            _ => Ok(Stmt::CONTINUE { label, span }),
+        }
+    }
+
+    /// statements span from their first token to the last one they consumed
+    fn parse_next(&mut self) -> Result<Stmt, Box<Diagnostic>>{
+        let cur_token = self.in_stream.next()?;
         match cur_token.token_type {
             TokenType::EOF => {
-                Node::new_eof()
+                Ok(Stmt::EOF)
             }
             TokenType::SEMICOLON => {
-                Node::new(NodeType::EOL, String::from(""))
+                Ok(Stmt::EMPTY { span: cur_token.span })
             }
             TokenType::WHILE => {
-                self.parse_while()
+                self.parse_while(None)
+            }
+            TokenType::FOR => {
+                self.parse_for(None)
             }
             TokenType::IF => {
                 self.parse_if()
@@ -297,53 +699,62 @@ impl ASTStream{
                     TokenType::ASSIGN => {
                         self.parse_change()
                     }
+                    TokenType::LSQUARE => {
+                        self.parse_set()
+                    }
                     _ => {
-                        self.err("VAR FALLOWED AN WRONG CH");
-                        Node::new_eof()
+                        match (next_token.token_type, Builtin::from_name(&cur_token.value)) {
+                            (TokenType::LBRAC, Some(builtin)) => Ok(Stmt::BUILTIN(self.parse_builtin(builtin)?)),
+                            _ => Err(self.err("E0201", &format!("{} should be followed by : or =", cur_token.value)).into()),
+                        }
                     }
                 }
             }
             TokenType::PUT => {
                 self.parse_put()
             }
+            TokenType::FUNCTION => {
+                self.parse_function()
+            }
+            TokenType::CALL => {
+                Ok(Stmt::CALL(self.parse_call()?))
+            }
+            TokenType::RET => {
+                self.parse_ret()
+            }
+            TokenType::BREAK | TokenType::CONTINUE => {
+                self.parse_jump()
+            }
             _ => {
-                println!("cur token: {:?}", cur_token);
-                self.err("WRONG START OF LINE");
-                Node::new_eof()
+                Err(self.err("E0201", &format!("WRONG START OF LINE: {}", cur_token.value)).into())
             }
         }
     }
 }
 
 
-impl Stream<Node> for ASTStream{
-    fn next(&mut self) -> Node {
+impl Stream<Stmt> for ASTStream{
+    fn next(&mut self) -> Result<Stmt, Box<Diagnostic>> {
         self.cur = self.next.clone();
-        self.next = self.parse_next();
-        self.cur.clone()
+        self.next = self.parse_next()?;
+        Ok(self.cur.clone())
     }
-    fn peek(&self) -> Node {
+    fn peek(&self) -> Stmt {
         self.next.clone()
     }
-    fn current(&self) -> Node {
+    fn current(&self) -> Stmt {
         self.cur.clone()
     }
-    fn reset(&mut self) {
-        self.in_stream.reset();
-        self.cur = Node::new_eof();
-        self.next = self.parse_next();
+    fn reset(&mut self) -> Result<(), Box<Diagnostic>> {
+        self.in_stream.reset()?;
+        self.cur = Stmt::EOF;
+        self.next = self.parse_next()?;
+        Ok(())
     }
     fn eof(&self) -> bool {
-        match self.next.node_type {
-            NodeType::EOF => {
-                true
-            }
-            _ => {
-                false
-            }
-        }
+        self.next.is_eof()
     }
-    fn err(&self, msg: &str) {
-        self.in_stream.err(msg);
+    fn err(&self, code: &'static str, msg: &str) -> Diagnostic {
+        self.in_stream.err(code, msg)
     }
-}
\ No newline at end of file
+}
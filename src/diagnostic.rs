@@ -0,0 +1,67 @@
+use std::fmt;
+
+use crate::util::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity{
+    ERROR,
+    WARNING,
+    NOTE,
+    HELP
+}
+
+impl fmt::Display for Severity{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::ERROR => write!(f, "error"),
+            Severity::WARNING => write!(f, "warning"),
+            Severity::NOTE => write!(f, "note"),
+            Severity::HELP => write!(f, "help"),
+        }
+    }
+}
+
+/// what every stage hands back instead of panicking
+/// codes: E00xx reading the source, E01xx lexing, E02xx parsing, E03xx type checking, E04xx running
+#[derive(Debug, Clone)]
+pub struct Diagnostic{
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    /// notes and help attached to this one, they have no code of their own
+    pub children: Vec<Diagnostic>
+}
+
+impl Diagnostic{
+    pub fn new(severity: Severity, code: &'static str, message: String, span: Option<Span>) -> Diagnostic{
+        Diagnostic { severity, code, message, span, children: vec![] }
+    }
+    pub fn error(code: &'static str, message: String, span: Option<Span>) -> Diagnostic{
+        Diagnostic::new(Severity::ERROR, code, message, span)
+    }
+    /// a note pointing somewhere else in the source, like where a variable was declared
+    /// left out when span is None, a name from an earlier run has nowhere in this source to point at
+    pub fn with_note(mut self, message: String, span: Option<Span>) -> Diagnostic{
+        if span.is_some() {
+            self.children.push(Diagnostic::new(Severity::NOTE, "", message, span));
+        }
+        self
+    }
+    pub fn with_help(mut self, message: String) -> Diagnostic{
+        self.children.push(Diagnostic::new(Severity::HELP, "", message, None));
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " (row: {} col: {})", span.row, span.col)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic{}
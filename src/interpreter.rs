@@ -0,0 +1,143 @@
+use std::{fmt, io::Read};
+
+use crate::{ast::{Expr, Stmt}, diagnostic::Diagnostic, parser::Parser, render::Renderer, text_stream::TextStream, util::{BaseType, Value}};
+
+/// what the --> line of an error calls source that did not come from a file
+const INPUT_NAME: &str = "<input>";
+
+/// why a run stopped, every diagnostic and the source they point into
+#[derive(Debug, Clone)]
+pub struct RunError{
+    pub diagnostics: Vec<Diagnostic>,
+    /// the path of the file, or <input>
+    pub name: String,
+    content: Vec<u8>
+}
+
+impl RunError{
+    /// content is the source the diagnostics point into
+    pub fn new(diagnostics: Vec<Diagnostic>, name: &str, content: &[u8]) -> RunError{
+        RunError { diagnostics, name: name.to_string(), content: content.to_vec() }
+    }
+
+    pub fn content(&self) -> &[u8]{
+        &self.content
+    }
+
+    /// every diagnostic with its source snippet, the way the binary prints them
+    pub fn render(&self, color: bool) -> String{
+        let renderer = Renderer::new(&self.content, &self.name, color);
+        let rendered: Vec<String> = self.diagnostics.iter().map(|diagnostic| renderer.render(diagnostic)).collect();
+        rendered.join("\n")
+    }
+}
+
+impl fmt::Display for RunError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+impl std::error::Error for RunError{}
+
+/// runs double basic programs
+/// globals and functions stay around between runs, so a later program can use what an earlier one declared
+/// it runs on the calling thread, a deep recursion needs a big stack there, the binary uses a thread with 64MB
+pub struct Interpreter{
+    parser: Parser
+}
+
+impl Default for Interpreter{
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter{
+    pub fn new() -> Interpreter{
+        Interpreter { parser: Parser::new() }
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), RunError>{
+        self.run_text(TextStream::from(source), INPUT_NAME)
+    }
+
+    pub fn run_file(&mut self, path: &str) -> Result<(), RunError>{
+        self.read_and_run(TextStream::new(path), path)
+    }
+
+    /// runs whatever reader holds up to its end, like stdin or a socket
+    pub fn run_reader<R: Read>(&mut self, reader: R, name: &str) -> Result<(), RunError>{
+        self.read_and_run(TextStream::from_reader(reader, name), name)
+    }
+
+    fn read_and_run(&mut self, text: Result<TextStream, Box<Diagnostic>>, name: &str) -> Result<(), RunError>{
+        match text {
+            Ok(text) => self.run_text(text, name),
+            Err(e) => Err(RunError::new(vec![*e], name, &[])),
+        }
+    }
+
+    /// name is what the --> line of an error calls the source
+    pub fn run_text(&mut self, text: TextStream, name: &str) -> Result<(), RunError>{
+        //kept aside so the error can quote it after the parser is done with it
+        let content = text.content().to_vec();
+        self.parser.run(text).map_err(|diagnostics| RunError::new(diagnostics, name, &content))
+    }
+
+    /// the syntax tree of text, globals and functions from earlier runs count as declared
+    pub fn parse_text(&self, text: TextStream, name: &str) -> Result<Vec<Stmt>, RunError>{
+        let content = text.content().to_vec();
+        self.parser.parse(text).map_err(|errors| RunError::new(errors, name, &content))
+    }
+
+    /// parses and type checks text without running any of it
+    pub fn check_text(&self, text: TextStream, name: &str) -> Result<(), RunError>{
+        let content = text.content().to_vec();
+        let program = self.parser.parse(text).map_err(|errors| RunError::new(errors, name, &content))?;
+        let errors = self.parser.check(&program);
+        if !errors.is_empty() {
+            return Err(RunError::new(errors, name, &content));
+        }
+        Ok(())
+    }
+
+    /// text as one expression, checked against the globals and functions declared so far
+    pub fn parse_expression_text(&self, text: TextStream, name: &str) -> Result<Expr, RunError>{
+        let content = text.content().to_vec();
+        self.parser.parse_expression(text).map_err(|errors| RunError::new(errors, name, &content))
+    }
+
+    pub fn type_of_text(&self, text: TextStream, name: &str) -> Result<BaseType, RunError>{
+        let content = text.content().to_vec();
+        let expr = self.parser.parse_expression(text).map_err(|errors| RunError::new(errors, name, &content))?;
+        self.parser.type_of(&expr).map_err(|errors| RunError::new(errors, name, &content))
+    }
+
+    /// runs text as one expression and gives back its value, None for a CALL that returns nothing
+    pub fn eval_text(&mut self, text: TextStream, name: &str) -> Result<Option<Value>, RunError>{
+        let content = text.content().to_vec();
+        let expr = self.parser.parse_expression(text).map_err(|errors| RunError::new(errors, name, &content))?;
+        self.parser.type_of(&expr).map_err(|errors| RunError::new(errors, name, &content))?;
+        self.parser.eval(&expr).map_err(|e| RunError::new(vec![*e], name, &content))
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, RunError>{
+        self.eval_text(TextStream::from(source), INPUT_NAME)
+    }
+
+    /// every global and its value, sorted by name
+    pub fn globals(&self) -> Vec<(String, Value)>{
+        self.parser.globals_sorted()
+    }
+
+    /// declares a global before the next run, replacing one of the same name
+    pub fn set(&mut self, name: &str, value: Value){
+        self.parser.set_global(name, value);
+    }
+
+    /// a global as the last run left it, locals are gone once their block ends
+    pub fn get(&self, name: &str) -> Option<Value>{
+        self.parser.get_global(name)
+    }
+}
@@ -0,0 +1,30 @@
+//! double basic, a small typed basic
+//!
+//! ```
+//! use double_basic::{Interpreter, Value};
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.set("N", Value::NUMBER(4.0));
+//! interpreter.run("SQUARE: NUMBER = N * N;").unwrap();
+//! assert_eq!(interpreter.get("SQUARE"), Some(Value::NUMBER(16.0)));
+//!
+//! let error = interpreter.run("PUT M;").unwrap_err();
+//! assert_eq!(error.diagnostics[0].code, "E0202");
+//! ```
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod ast;
+pub mod diagnostic;
+pub mod render;
+pub mod util;
+pub mod text_stream;
+pub mod token_stream;
+mod ast_stream;
+mod type_checker;
+mod parser;
+mod interpreter;
+
+pub use diagnostic::{Diagnostic, Severity};
+pub use interpreter::{Interpreter, RunError};
+pub use text_stream::TextStream;
+pub use util::{BaseType, Value};
@@ -1,43 +1,157 @@
-use std::io::stdin;
+#![allow(clippy::upper_case_acronyms)]
 
-mod util;
-mod text_stream;
-mod token_stream;
-mod ast_stream;
-mod parser;
+use std::{env, io::{stderr, stdin, IsTerminal}, process, thread};
 
-fn main() {
-    println!("Type in the file path (type in !number to run sample code)");
-    let mut buf = String::from("");
-    stdin().read_line(&mut buf).unwrap();
-    if buf.starts_with('!'){
-        buf = buf[1..].to_string();
-        buf = buf.trim_end().to_string();
-        buf = format!("./samples/sample{}.bb", buf);
+use double_basic::{token_stream::tokenize, Interpreter, RunError, TextStream};
+
+mod repl;
+
+const USAGE: &str = "\
+usage: double_basic <command> <file | - | -e code>
+       double_basic repl
+
+commands:
+    run       parse, type check and run the program
+    check     parse and type check the program without running it
+    tokens    print the tokens the lexer reads
+    ast       print the syntax tree the parser builds
+    repl      type statements and expressions one at a time, :help in it lists its commands
+
+the program is read from file, from stdin when it is -, or from the code after -e
+
+options:
+    -e code       the program itself instead of a file
+    -h, --help    print this help
+
+exit codes:
+    0    everything went fine
+    1    the program has errors and did not run
+    2    the command line is wrong
+    3    the program failed while running
+";
+
+const EXIT_PROGRAM: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_RUNTIME: i32 = 3;
+
+//the deepest nesting the interpreter allows needs about 20MB of stack in a debug build, more than the main thread has
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+enum Command {
+    RUN(Input),
+    CHECK(Input),
+    TOKENS(Input),
+    AST(Input),
+    REPL
+}
+
+/// where the program comes from
+enum Input {
+    FILE(String),
+    STDIN,
+    INLINE(String)
+}
+
+impl Input {
+    /// the source and what the --> line of an error calls it
+    fn read(&self) -> Result<(TextStream, String), RunError> {
+        let (text, name) = match self {
+            Input::FILE(path) => (TextStream::new(path), path.clone()),
+            Input::STDIN => (TextStream::from_reader(stdin(), "<stdin>"), String::from("<stdin>")),
+            Input::INLINE(code) => (Ok(TextStream::from(code.as_str())), String::from("<-e>")),
+        };
+        match text {
+            Ok(text) => Ok((text, name)),
+            Err(e) => Err(RunError::new(vec![*e], &name, &[])),
+        }
+    }
+}
+
+/// None when help was asked for
+fn parse_args(args: &[String]) -> Result<Option<Command>, String> {
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(None);
     }
-    else{
-        buf = buf.trim_end().to_string();
+    let command: fn(Input) -> Command = match args[0].as_str() {
+        "run" => Command::RUN,
+        "check" => Command::CHECK,
+        "tokens" => Command::TOKENS,
+        "ast" => Command::AST,
+        "repl" if args.len() == 1 => return Ok(Some(Command::REPL)),
+        "repl" => return Err(String::from("repl does not take a program, use :load in it")),
+        other => return Err(format!("unknown command {}", other)),
+    };
+    let input = match &args[1..] {
+        [flag, code] if flag == "-e" => Input::INLINE(code.clone()),
+        [flag] if flag == "-e" => return Err(String::from("-e needs the code to run after it")),
+        [dash] if dash == "-" => Input::STDIN,
+        [path] if !path.starts_with('-') => Input::FILE(path.clone()),
+        [option] => return Err(format!("unknown option {}", option)),
+        [] => return Err(String::from("there should be a file, - or -e code after the command")),
+        _ => return Err(String::from("only one program can be given")),
+    };
+    Ok(Some(command(input)))
+}
+
+fn execute(command: Command) -> Result<(), RunError> {
+    let mut interpreter = Interpreter::new();
+    match command {
+        Command::RUN(input) => {
+            let (text, name) = input.read()?;
+            interpreter.run_text(text, &name)
+        }
+        Command::CHECK(input) => {
+            let (text, name) = input.read()?;
+            interpreter.check_text(text, &name)
+        }
+        Command::TOKENS(input) => {
+            let (text, name) = input.read()?;
+            let content = text.content().to_vec();
+            let tokens = tokenize(text).map_err(|errors| RunError::new(errors, &name, &content))?;
+            for token in tokens {
+                //escaped so a string holding a newline stays on its line
+                println!("{}:{}\t{:?}\t{}", token.span.row, token.span.col, token.token_type, token.value.escape_debug());
+            }
+            Ok(())
+        }
+        Command::AST(input) => {
+            let (text, name) = input.read()?;
+            for stmt in interpreter.parse_text(text, &name)? {
+                println!("{:#?}", stmt);
+            }
+            Ok(())
+        }
+        Command::REPL => {
+            repl::run();
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let result = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || execute(command))
+        .unwrap()
+        .join()
+        .unwrap();
+    if let Err(e) = result {
+        let color = stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+        eprint!("{}", e.render(color));
+        //E04xx are the errors raised while running
+        let runtime = e.diagnostics.iter().any(|diagnostic| diagnostic.code.starts_with("E04"));
+        process::exit(if runtime { EXIT_RUNTIME } else { EXIT_PROGRAM });
     }
-    println!("{}", buf);
-    let mut parser = parser::Parser::open_file(&buf);
-    parser.run();
-    // let debug = false;
-    // while !stream.eof() {
-    //     let tmp = stream.next();
-    //     match tmp.node_type {
-    //         NodeType::EOL => {
-                // println!("EOL");
-    //         }
-    //         _ => {
-    //             if debug {
-    //                 for _ in 0..2{
-    //                     print!("-------------------------------------");
-    //                 }
-    //                 println!("---------------------------");
-    //                 println!("{:#?}", tmp);
-    //             }
-    //             parser.run(tmp);
-    //         }
-    //     }
-    // }
 }
@@ -1,218 +1,886 @@
-use core::panic;
-use std::{collections::HashMap, iter};
+use std::{collections::{BTreeMap, HashMap}, rc::Rc};
 
-use crate::{ast_stream::{ASTStream, Node}, util::{NodeType, BaseType, Stream}};
+use crate::{ast::{BinaryOp, Builtin, BuiltinCall, Call, Expr, Function, Stmt, UnaryOp}, ast_stream::ASTStream, diagnostic::Diagnostic, text_stream::TextStream, token_stream::TokenStream, type_checker::TypeChecker, util::{BaseType, Prelude, Span, Stream, Value}};
 
-pub struct Parser {
-    in_stream: ASTStream,
+//how many calls, blocks and expressions can be open inside one another, each takes up to about 5KB of rust stack in a debug build
+//a recursion 200 deep through a few nested loops and IFs fits, the binary gives the interpreter a stack big enough for all of it
+const MAX_DEPTH: usize = 4096;
+
+/// what a statement tells the block running it
+enum Flow {
+    Next,
+    Ret,
+    //the label of the loop to leave or go on with, None for the innermost one
+    Break(Option<String>),
+    Continue(Option<String>)
+}
+
+/// what a loop labelled label does once its body ends with flow, Some to stop and hand it on
+/// a BREAK or CONTINUE for an outer loop goes on up
+fn after_body(flow: Flow, label: &Option<String>) -> Option<Flow> {
+    match flow {
+        Flow::Break(target) if target.is_none() || target == *label => Some(Flow::Next),
+        Flow::Continue(target) if target.is_none() || target == *label => None,
+        Flow::Next => None,
+        flow => Some(flow),
+    }
+}
+
+/// a whole number from 0 up to len - 1, anything else is out of bounds
+fn index_of(val: f64, len: usize, span: Span) -> Result<usize, Box<Diagnostic>> {
+    if val.fract() != 0.0 {
+        return Err(Diagnostic::error("E0408", format!("index {} is not a whole number", val), Some(span)).into());
+    }
+    if val < 0.0 || val >= len as f64 {
+        return Err(Diagnostic::error("E0408", format!("index {} is out of bounds for a list of length {}", val, len), Some(span))
+            .with_help(String::from("lists count from 0, the last item is at LEN(list) - 1")).into());
+    }
+    Ok(val as usize)
+}
+
+/// the item of a list at a NUMBER index, or of a map at a STRING key
+fn item_of<'a>(container: &'a mut Value, key: &Value, span: Span) -> Result<&'a mut Value, Box<Diagnostic>> {
+    match (container, key) {
+        (Value::LIST(_, items), Value::NUMBER(val)) => {
+            let i = index_of(*val, items.len(), span)?;
+            Ok(&mut items[i])
+        }
+        (Value::MAP(_, entries), Value::STRING(key)) => {
+            entries.get_mut(key).ok_or_else(|| missing_key(key, span).into())
+        }
+        _ => Err(Diagnostic::error("E0405", String::from("not a list or map"), Some(span)).into()),
+    }
+}
+
+fn missing_key(key: &str, span: Span) -> Diagnostic {
+    Diagnostic::error("E0410", format!("there is no key {:?} in the map", key), Some(span))
+        .with_help(String::from("HAS(map, key) tells whether a key is there"))
+}
+
+/// variables declared in one block
+#[derive(Default)]
+struct Scope {
     num_var: HashMap<String, f64>,
-    str_var: HashMap<String, String>
+    str_var: HashMap<String, String>,
+    //always a Value::LIST, which keeps the item type
+    list_var: HashMap<String, Value>,
+    //always a Value::MAP
+    map_var: HashMap<String, Value>
+}
+
+/// blocks open in one CALL, innermost last, and the value its RET left behind
+#[derive(Default)]
+struct Frame {
+    scopes: Vec<Scope>,
+    ret_num: f64,
+    ret_str: String,
+    ret_list: Vec<Value>,
+    ret_map: BTreeMap<String, Value>
+}
+
+impl Frame {
+    fn new(scope: Scope) -> Frame {
+        Frame { scopes: vec![scope], ..Default::default() }
+    }
+}
+
+/// runs programs one after another, the globals and functions each one leaves behind are there for the next
+pub struct Parser {
+    functions: HashMap<String, Rc<Function>>,
+    //frames[0] is the top level, its first scope holds the globals
+    frames: Vec<Frame>,
+    //calls, blocks and expressions being run right now, see MAX_DEPTH
+    depth: usize
 }
 
 impl Parser {
-    pub fn open_file(file_name: &str) -> Parser {
+    pub fn new() -> Parser {
         Parser {
-            in_stream: ASTStream::new(file_name),
-            num_var: HashMap::new(),
-            str_var: HashMap::new()
+            functions: HashMap::new(),
+            frames: vec![Frame::new(Scope::default())],
+            depth: 0
+        }
+    }
+
+    fn globals(&self) -> &Scope {
+        &self.frames[0].scopes[0]
+    }
+
+    /// what the next program can use without declaring it
+    pub fn prelude(&self) -> Prelude {
+        let globals = self.globals();
+        let nums = globals.num_var.keys().map(|name| (name.clone(), BaseType::NUMBER));
+        let strs = globals.str_var.keys().map(|name| (name.clone(), BaseType::STRING));
+        let lists = globals.list_var.iter().chain(&globals.map_var).map(|(name, val)| (name.clone(), val.val_type()));
+        let funcs = self.functions.iter()
+            .map(|(name, func)| (name.clone(), (func.params.iter().map(|param| param.val_type.clone()).collect(), func.ret_type.clone())))
+            .collect();
+        Prelude { vars: nums.chain(strs).chain(lists).collect(), funcs }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let globals = self.globals();
+        match (globals.num_var.get(name), globals.str_var.get(name)) {
+            (Some(val), _) => Some(Value::NUMBER(*val)),
+            (_, Some(val)) => Some(Value::STRING(val.clone())),
+            _ => globals.list_var.get(name).or(globals.map_var.get(name)).cloned(),
+        }
+    }
+
+    /// every global sorted by name
+    pub fn globals_sorted(&self) -> Vec<(String, Value)> {
+        let globals = self.globals();
+        let nums = globals.num_var.iter().map(|(name, val)| (name.clone(), Value::NUMBER(*val)));
+        let strs = globals.str_var.iter().map(|(name, val)| (name.clone(), Value::STRING(val.clone())));
+        let lists = globals.list_var.iter().chain(&globals.map_var).map(|(name, val)| (name.clone(), val.clone()));
+        let mut all: Vec<(String, Value)> = nums.chain(strs).chain(lists).collect();
+        all.sort_by(|a, b| a.0.cmp(&b.0));
+        all
+    }
+
+    /// a global keeps one type, setting it to another one replaces it
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        let globals = &mut self.frames[0].scopes[0];
+        globals.num_var.remove(name);
+        globals.str_var.remove(name);
+        globals.list_var.remove(name);
+        globals.map_var.remove(name);
+        match value {
+            Value::NUMBER(val) => {
+                globals.num_var.insert(name.to_string(), val);
+            }
+            Value::STRING(val) => {
+                globals.str_var.insert(name.to_string(), val);
+            }
+            Value::LIST(..) => {
+                globals.list_var.insert(name.to_string(), value);
+            }
+            Value::MAP(..) => {
+                globals.map_var.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    /// runtime errors point at the node being run
+    fn err(&self, code: &'static str, msg: String, span: Span) -> Diagnostic {
+        Diagnostic::error(code, msg, Some(span))
+    }
+
+    /// innermost block of the running CALL first, then the globals
+    fn scope_of(&mut self, has: impl Fn(&Scope) -> bool) -> &mut Scope {
+        let depth = self.frames.len() - 1;
+        match self.frames[depth].scopes.iter().rposition(&has) {
+            Some(i) => &mut self.frames[depth].scopes[i],
+            None => &mut self.frames[0].scopes[0],
         }
     }
 
-    fn get_val_num(&self, root: Node) -> f64 {
-        match root.node_type {
-            NodeType::VAL => {
-                if root.identity == "" {
-                    return root.num_val;
+    fn num_var(&mut self, name: &str) -> Option<&mut f64> {
+        self.scope_of(|scope| scope.num_var.contains_key(name)).num_var.get_mut(name)
+    }
+
+    fn str_var(&mut self, name: &str) -> Option<&mut String> {
+        self.scope_of(|scope| scope.str_var.contains_key(name)).str_var.get_mut(name)
+    }
+
+    fn list_var(&mut self, name: &str) -> Option<&mut Value> {
+        self.scope_of(|scope| scope.list_var.contains_key(name)).list_var.get_mut(name)
+    }
+
+    fn map_var(&mut self, name: &str) -> Option<&mut Value> {
+        self.scope_of(|scope| scope.map_var.contains_key(name)).map_var.get_mut(name)
+    }
+
+    /// where a declaration lands, the innermost block of the running CALL
+    fn innermost(&mut self) -> &mut Scope {
+        self.frames.last_mut().unwrap().scopes.last_mut().unwrap()
+    }
+
+    /// runs f one level deeper, an error instead once MAX_DEPTH levels are open
+    fn nested<T>(&mut self, span: Span, f: impl FnOnce(&mut Parser) -> Result<T, Box<Diagnostic>>) -> Result<T, Box<Diagnostic>> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.err("E0403", String::from("stack overflow, calls, blocks or expressions are nested too deep"), span)
+                .with_help(String::from("a recursion needs a case that ends it without calling itself")).into());
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn call(&mut self, call: &Call) -> Result<Frame, Box<Diagnostic>> {
+        let func = match self.functions.get(&call.name) {
+            Some(func) => Rc::clone(func),
+            None => return Err(self.err("E0402", format!("function {} is not defined", call.name), call.span).into()),
+        };
+        let mut params = Scope::default();
+        for (param, arg) in func.params.iter().zip(&call.args) {
+            match param.val_type {
+                BaseType::NUMBER => {
+                    params.num_var.insert(param.name.clone(), self.get_val_num(arg)?);
+                }
+                BaseType::STRING => {
+                    params.str_var.insert(param.name.clone(), self.get_val_str(arg)?);
+                }
+                //a list or map is copied, the function cannot change the one it was given
+                BaseType::LIST(ref item) => {
+                    params.list_var.insert(param.name.clone(), Value::LIST((**item).clone(), self.get_val_list(arg)?));
                 }
-                else {
-                    return self.num_var.get(&root.identity).unwrap().clone();
+                BaseType::MAP(ref item) => {
+                    params.map_var.insert(param.name.clone(), Value::MAP((**item).clone(), self.get_val_map(arg)?));
+                }
+                BaseType::VOID => {
+                    return Err(self.err("E0406", format!("void type in param {}", param.name), param.span).into());
                 }
             }
-            NodeType::EXPRESSION => {
-                let op = root.str_val.clone();
-                match op.as_str() {
-                    "+" => {
-                        return self.get_val_num(root.l_children[0].clone()) + self.get_val_num(root.r_children[0].clone());
-                    }
-                    "-" => {
-                        return self.get_val_num(root.l_children[0].clone()) - self.get_val_num(root.r_children[0].clone());
-                    }
-                    "*" => {
-                        return self.get_val_num(root.l_children[0].clone()) * self.get_val_num(root.r_children[0].clone());
-                    }
-                    "/" => {
-                        return self.get_val_num(root.l_children[0].clone()) / self.get_val_num(root.r_children[0].clone());
-                    }
-                    "%" => {
-                        return self.get_val_num(root.l_children[0].clone()) % self.get_val_num(root.r_children[0].clone());
-                    }
-                    "^" => {
-                        return self.get_val_num(root.l_children[0].clone()).powf(self.get_val_num(root.r_children[0].clone()));
-                    }
-                    ">" => {
-                        return if self.get_val_num(root.l_children[0].clone()) > self.get_val_num(root.r_children[0].clone()) { 1.0 } else { 0.0 };
-                    }
-                    "<" => {
-                        return if self.get_val_num(root.l_children[0].clone()) < self.get_val_num(root.r_children[0].clone()) { 1.0 } else { 0.0 };
-                    }
-                    "<=" => {
-                        return if self.get_val_num(root.l_children[0].clone()) <= self.get_val_num(root.r_children[0].clone()) { 1.0 } else { 0.0 };
-                    }
-                    ">=" => {
-                        return if self.get_val_num(root.l_children[0].clone()) >= self.get_val_num(root.r_children[0].clone()) { 1.0 } else { 0.0 };
-                    }
-                    "==" => {
-                        return if self.get_val_num(root.l_children[0].clone()) == self.get_val_num(root.r_children[0].clone()) { 1.0 } else { 0.0 };
-                    }
-                    "!=" => {
-                        return if self.get_val_num(root.l_children[0].clone()) != self.get_val_num(root.r_children[0].clone()) { 1.0 } else { 0.0 };
-                    }
-                    "&&" => {
-                        return if self.get_val_num(root.l_children[0].clone()) != 0.0 && self.get_val_num(root.r_children[0].clone()) != 0.0 { 1.0 } else { 0.0 };
-                    }
-                    "||" => {
-                        return if self.get_val_num(root.l_children[0].clone()) != 0.0 || self.get_val_num(root.r_children[0].clone()) != 0.0 { 1.0 } else { 0.0 };
-                    }
-                    "!" => {
-                        return if self.get_val_num(root.r_children[0].clone()) == 0.0 { 1.0 } else { 0.0 };
-                    }
-                    _ => {
-                        panic!("Not a valid operator");
-                    }
+        }
+        self.frames.push(Frame::new(params));
+        let flow = self.run_block(&func.body);
+        let frame = self.frames.pop().unwrap();
+        match (flow?, &func.ret_type) {
+            (Flow::Next, BaseType::NUMBER | BaseType::STRING | BaseType::LIST(_) | BaseType::MAP(_)) => {
+                Err(self.err("E0404", format!("function {} ended without RET", func.name), call.span)
+                    .with_help(format!("every path through {} has to end with a RET", func.name)).into())
+            }
+            _ => Ok(frame),
+        }
+    }
+
+    fn get_val_num(&mut self, expr: &Expr) -> Result<f64, Box<Diagnostic>> {
+        self.nested(expr.span(), |parser| parser.num_of(expr))
+    }
+
+    fn num_of(&mut self, expr: &Expr) -> Result<f64, Box<Diagnostic>> {
+        match expr {
+            Expr::NUMBER { val, .. } => {
+                Ok(*val)
+            }
+            Expr::VAR { name, span, .. } => {
+                match self.num_var(name) {
+                    Some(val) => Ok(*val),
+                    None => Err(self.err("E0401", format!("variable {} is not defined", name), *span).into()),
+                }
+            }
+            Expr::CALL(call) => {
+                Ok(self.call(call)?.ret_num)
+            }
+            Expr::BUILTIN(call) => {
+                match self.builtin(call)? {
+                    Some(Value::NUMBER(val)) => Ok(val),
+                    _ => Err(self.err("E0405", String::from("not a number or expression"), call.span).into()),
                 }
             }
-            _ => {
-                panic!("ERROR: not a number or expression");
+            Expr::INDEX { list, index, span, .. } => {
+                match self.get_item(list, index)? {
+                    Value::NUMBER(val) => Ok(val),
+                    _ => Err(self.err("E0405", String::from("not a number or expression"), *span).into()),
+                }
+            }
+            Expr::UNARY { op, operand, .. } => {
+                let val = self.get_val_num(operand)?;
+                match op {
+                    UnaryOp::NOT => Ok(if val == 0.0 { 1.0 } else { 0.0 }),
+                    UnaryOp::NEG => Ok(-val),
+                }
+            }
+            //strings compare by their chars, "B" > "A" and "AB" > "A"
+            Expr::BINARY { op, l, r, span } if l.val_type() == BaseType::STRING => {
+                let (l, r) = (self.get_val_str(l)?, self.get_val_str(r)?);
+                let val = match op {
+                    BinaryOp::EQUAL => l == r,
+                    BinaryOp::NOTEQUAL => l != r,
+                    BinaryOp::GREATER => l > r,
+                    BinaryOp::LESS => l < r,
+                    BinaryOp::GOE => l >= r,
+                    BinaryOp::LOE => l <= r,
+                    _ => return Err(self.err("E0405", format!("{} is not a valid operator on STRING", op), *span).into()),
+                };
+                Ok(if val { 1.0 } else { 0.0 })
+            }
+            Expr::BINARY { op, l, r, span } => {
+                let l = self.get_val_num(l)?;
+                //short circuit before touching the r side
+                match op {
+                    BinaryOp::AND if l == 0.0 => return Ok(0.0),
+                    BinaryOp::OR if l != 0.0 => return Ok(1.0),
+                    _ => {}
+                }
+                let r = self.get_val_num(r)?;
+                match op {
+                    BinaryOp::ADD => Ok(l + r),
+                    BinaryOp::SUB => Ok(l - r),
+                    BinaryOp::MUL => Ok(l * r),
+                    BinaryOp::DIV => Ok(l / r),
+                    BinaryOp::MOD => Ok(l % r),
+                    BinaryOp::POW => Ok(l.powf(r)),
+                    BinaryOp::GREATER => Ok(if l > r { 1.0 } else { 0.0 }),
+                    BinaryOp::LESS => Ok(if l < r { 1.0 } else { 0.0 }),
+                    BinaryOp::LOE => Ok(if l <= r { 1.0 } else { 0.0 }),
+                    BinaryOp::GOE => Ok(if l >= r { 1.0 } else { 0.0 }),
+                    BinaryOp::EQUAL => Ok(if l == r { 1.0 } else { 0.0 }),
+                    BinaryOp::NOTEQUAL => Ok(if l != r { 1.0 } else { 0.0 }),
+                    BinaryOp::AND | BinaryOp::OR => Ok(if r != 0.0 { 1.0 } else { 0.0 }),
+                    BinaryOp::CAT => Err(self.err("E0405", format!("{} is not a valid operator on NUMBER", op), *span).into()),
+                }
+            }
+            Expr::STRING { span, .. } | Expr::LIST { span, .. } | Expr::MAP { span, .. } => {
+                Err(self.err("E0405", String::from("not a number or expression"), *span).into())
             }
         }
     }
 
-    fn get_val_str(&self, root: Node) -> String {
-        match root.node_type {
-            NodeType::VAL => {
-                if root.identity == "" {
-                    return root.str_val;
+    fn get_val_str(&mut self, expr: &Expr) -> Result<String, Box<Diagnostic>> {
+        self.nested(expr.span(), |parser| parser.str_of(expr))
+    }
+
+    fn str_of(&mut self, expr: &Expr) -> Result<String, Box<Diagnostic>> {
+        match expr {
+            Expr::STRING { val, .. } => {
+                Ok(val.clone())
+            }
+            Expr::VAR { name, span, .. } => {
+                match self.str_var(name) {
+                    Some(val) => Ok(val.clone()),
+                    None => Err(self.err("E0401", format!("variable {} is not defined", name), *span).into()),
                 }
-                else {
-                    return self.str_var.get(&root.identity).unwrap().clone();
+            }
+            Expr::CALL(call) => {
+                Ok(self.call(call)?.ret_str)
+            }
+            Expr::BUILTIN(call) => {
+                match self.builtin(call)? {
+                    Some(Value::STRING(val)) => Ok(val),
+                    _ => Err(self.err("E0405", String::from("not a string or expression"), call.span).into()),
                 }
             }
-            NodeType::EXPRESSION => {
-                let op = root.str_val.clone();
-                match op.as_str() {
-                    "|" => {
-                        return self.get_val_str(root.l_children[0].clone()) + &self.get_val_str(root.r_children[0].clone());
-                    }
-                    _ => {
-                        panic!("Not a valid operator");
-                    }
+            Expr::INDEX { list, index, span, .. } => {
+                match self.get_item(list, index)? {
+                    Value::STRING(val) => Ok(val),
+                    _ => Err(self.err("E0405", String::from("not a string or expression"), *span).into()),
                 }
             }
-            _ => {
-                panic!("ERROR: not a number or expression");
+            Expr::BINARY { op: BinaryOp::CAT, l, r, .. } => {
+                Ok(self.get_val_str(l)? + &self.get_val_str(r)?)
+            }
+            Expr::BINARY { op, span, .. } => {
+                Err(self.err("E0405", format!("{} is not a valid operator on STRING", op), *span).into())
+            }
+            Expr::NUMBER { span, .. } | Expr::UNARY { span, .. } | Expr::LIST { span, .. } | Expr::MAP { span, .. } => {
+                Err(self.err("E0405", String::from("not a string or expression"), *span).into())
             }
         }
     }
 
-    fn replace_num_var(&mut self, name: &str, val: f64) {
-        self.num_var.insert(name.to_string(), val);
+    /// the items of a list, copied out of any variable they come from
+    fn get_val_list(&mut self, expr: &Expr) -> Result<Vec<Value>, Box<Diagnostic>> {
+        self.nested(expr.span(), |parser| parser.list_of(expr))
+    }
+
+    fn list_of(&mut self, expr: &Expr) -> Result<Vec<Value>, Box<Diagnostic>> {
+        match expr {
+            Expr::LIST { items, .. } => {
+                items.iter().map(|item| self.get_val(item)).collect()
+            }
+            Expr::VAR { name, span, .. } => {
+                match self.list_var(name) {
+                    Some(Value::LIST(_, items)) => Ok(items.clone()),
+                    _ => Err(self.err("E0401", format!("variable {} is not defined", name), *span).into()),
+                }
+            }
+            Expr::CALL(call) => {
+                Ok(self.call(call)?.ret_list)
+            }
+            Expr::BUILTIN(call) => {
+                match self.builtin(call)? {
+                    Some(Value::LIST(_, items)) => Ok(items),
+                    _ => Err(self.err("E0405", String::from("not a list or expression"), call.span).into()),
+                }
+            }
+            Expr::INDEX { list, index, span, .. } => {
+                match self.get_item(list, index)? {
+                    Value::LIST(_, items) => Ok(items),
+                    _ => Err(self.err("E0405", String::from("not a list or expression"), *span).into()),
+                }
+            }
+            Expr::NUMBER { span, .. } | Expr::STRING { span, .. } | Expr::MAP { span, .. } | Expr::UNARY { span, .. } | Expr::BINARY { span, .. } => {
+                Err(self.err("E0405", String::from("not a list or expression"), *span).into())
+            }
+        }
     }
 
-    fn replace_str_var(&mut self, name: &str, val: String) {
-        self.str_var.insert(name.to_string(), val);
+    /// the entries of a map, copied out of any variable they come from
+    fn get_val_map(&mut self, expr: &Expr) -> Result<BTreeMap<String, Value>, Box<Diagnostic>> {
+        self.nested(expr.span(), |parser| parser.map_of(expr))
     }
 
-    fn reg_var(&mut self, root: Node) {
-        match root.val_type {
+    fn map_of(&mut self, expr: &Expr) -> Result<BTreeMap<String, Value>, Box<Diagnostic>> {
+        match expr {
+            //a key given twice keeps the last value
+            Expr::MAP { entries, .. } => {
+                let mut map = BTreeMap::new();
+                for (key, val) in entries {
+                    let key = self.get_val_str(key)?;
+                    map.insert(key, self.get_val(val)?);
+                }
+                Ok(map)
+            }
+            Expr::VAR { name, span, .. } => {
+                match self.map_var(name) {
+                    Some(Value::MAP(_, entries)) => Ok(entries.clone()),
+                    _ => Err(self.err("E0401", format!("variable {} is not defined", name), *span).into()),
+                }
+            }
+            Expr::CALL(call) => {
+                Ok(self.call(call)?.ret_map)
+            }
+            Expr::BUILTIN(call) => {
+                match self.builtin(call)? {
+                    Some(Value::MAP(_, entries)) => Ok(entries),
+                    _ => Err(self.err("E0405", String::from("not a map or expression"), call.span).into()),
+                }
+            }
+            Expr::INDEX { list, index, span, .. } => {
+                match self.get_item(list, index)? {
+                    Value::MAP(_, entries) => Ok(entries),
+                    _ => Err(self.err("E0405", String::from("not a map or expression"), *span).into()),
+                }
+            }
+            Expr::NUMBER { span, .. } | Expr::STRING { span, .. } | Expr::LIST { span, .. } | Expr::UNARY { span, .. } | Expr::BINARY { span, .. } => {
+                Err(self.err("E0405", String::from("not a map or expression"), *span).into())
+            }
+        }
+    }
+
+    /// a value of any type, for where every type is handled alike
+    fn get_val(&mut self, expr: &Expr) -> Result<Value, Box<Diagnostic>> {
+        match expr.val_type() {
+            BaseType::NUMBER => Ok(Value::NUMBER(self.get_val_num(expr)?)),
+            BaseType::STRING => Ok(Value::STRING(self.get_val_str(expr)?)),
+            BaseType::LIST(item) => Ok(Value::LIST(*item, self.get_val_list(expr)?)),
+            BaseType::MAP(item) => Ok(Value::MAP(*item, self.get_val_map(expr)?)),
+            BaseType::VOID => Err(self.err("E0406", String::from("void type in value"), expr.span()).into()),
+        }
+    }
+
+    /// a list or map variable, or an item inside one, to read or change it where it is
+    fn place(&mut self, expr: &Expr) -> Result<&mut Value, Box<Diagnostic>> {
+        //every index is worked out before the variable is borrowed
+        let mut keys = vec![];
+        let mut root = expr;
+        while let Expr::INDEX { list, index, .. } = root {
+            keys.push((self.get_val(index)?, index.span()));
+            root = list;
+        }
+        let (name, var) = match root {
+            Expr::VAR { name, val_type: BaseType::LIST(_), .. } => (name, self.list_var(name)),
+            Expr::VAR { name, val_type: BaseType::MAP(_), .. } => (name, self.map_var(name)),
+            _ => return Err(self.err("E0405", String::from("not a list or map variable"), expr.span()).into()),
+        };
+        let mut place = match var {
+            Some(var) => var,
+            None => return Err(Diagnostic::error("E0401", format!("variable {} is not defined", name), Some(root.span())).into()),
+        };
+        for (key, span) in keys.into_iter().rev() {
+            place = item_of(place, &key, span)?;
+        }
+        Ok(place)
+    }
+
+    /// list[index] or map[key], a variable is read where it is instead of copying all of it
+    fn get_item(&mut self, list: &Expr, index: &Expr) -> Result<Value, Box<Diagnostic>> {
+        let key = self.get_val(index)?;
+        let mut copy;
+        let container = if list.is_place() {
+            self.place(list)?
+        }
+        else {
+            copy = self.get_val(list)?;
+            &mut copy
+        };
+        Ok(item_of(container, &key, index.span())?.clone())
+    }
+
+    /// the builtins, None for PUSH and DELETE which give nothing back
+    fn builtin(&mut self, call: &BuiltinCall) -> Result<Option<Value>, Box<Diagnostic>> {
+        let arg = &call.args[0];
+        //worked out before the first argument is borrowed
+        let second = match call.args.get(1) {
+            Some(second) => Some(self.get_val(second)?),
+            None => None,
+        };
+        //a list or map variable is used where it is, anything else is worked out first
+        let mut copy;
+        let val = if arg.is_place() && matches!(arg.val_type(), BaseType::LIST(_) | BaseType::MAP(_)) {
+            self.place(arg)?
+        }
+        else {
+            copy = self.get_val(arg)?;
+            &mut copy
+        };
+        match (call.builtin, val, second) {
+            (Builtin::LEN, Value::STRING(val), _) => Ok(Some(Value::NUMBER(val.chars().count() as f64))),
+            (Builtin::LEN, Value::LIST(_, items), _) => Ok(Some(Value::NUMBER(items.len() as f64))),
+            (Builtin::LEN, Value::MAP(_, entries), _) => Ok(Some(Value::NUMBER(entries.len() as f64))),
+            (Builtin::PUSH, Value::LIST(_, items), Some(item)) => {
+                items.push(item);
+                Ok(None)
+            }
+            (Builtin::POP, Value::LIST(_, items), _) => {
+                match items.pop() {
+                    Some(item) => Ok(Some(item)),
+                    None => Err(Diagnostic::error("E0409", String::from("POP from an empty list"), Some(call.span)).into()),
+                }
+            }
+            (Builtin::HAS, Value::MAP(_, entries), Some(Value::STRING(key))) => {
+                Ok(Some(Value::NUMBER(if entries.contains_key(&key) { 1.0 } else { 0.0 })))
+            }
+            (Builtin::KEYS, Value::MAP(_, entries), _) => {
+                Ok(Some(Value::LIST(BaseType::STRING, entries.keys().map(|key| Value::STRING(key.clone())).collect())))
+            }
+            (Builtin::DELETE, Value::MAP(_, entries), Some(Value::STRING(key))) => {
+                match entries.remove(&key) {
+                    Some(_) => Ok(None),
+                    None => Err(missing_key(&key, call.args[1].span()).into()),
+                }
+            }
+            (builtin, _, _) => Err(Diagnostic::error("E0405", format!("wrong arguments to {:?}", builtin), Some(call.span)).into()),
+        }
+    }
+
+    /// assignments go to whichever block declared the name
+    fn replace_num_var(&mut self, name: &str, val: f64, span: Span) -> Result<(), Box<Diagnostic>> {
+        match self.num_var(name) {
+            Some(var) => *var = val,
+            None => return Err(self.err("E0401", format!("variable {} is not defined", name), span).into()),
+        }
+        Ok(())
+    }
+
+    /// the list keeps the item type it was declared with, so A = []; still knows it, the same for a map
+    fn replace_list_var(&mut self, name: &str, val: Vec<Value>, span: Span) -> Result<(), Box<Diagnostic>> {
+        match self.list_var(name) {
+            Some(Value::LIST(_, items)) => *items = val,
+            _ => return Err(self.err("E0401", format!("variable {} is not defined", name), span).into()),
+        }
+        Ok(())
+    }
+
+    fn replace_map_var(&mut self, name: &str, val: BTreeMap<String, Value>, span: Span) -> Result<(), Box<Diagnostic>> {
+        match self.map_var(name) {
+            Some(Value::MAP(_, entries)) => *entries = val,
+            _ => return Err(self.err("E0401", format!("variable {} is not defined", name), span).into()),
+        }
+        Ok(())
+    }
+
+    fn replace_str_var(&mut self, name: &str, val: String, span: Span) -> Result<(), Box<Diagnostic>> {
+        match self.str_var(name) {
+            Some(var) => *var = val,
+            None => return Err(self.err("E0401", format!("variable {} is not defined", name), span).into()),
+        }
+        Ok(())
+    }
+
+    fn reg_var(&mut self, name: &str, val_type: &BaseType, value: &Expr, span: Span) -> Result<(), Box<Diagnostic>> {
+        match val_type {
             BaseType::NUMBER => {
-                self.num_var.extend(iter::once((root.identity.clone(), self.get_val_num(root.l_children[0].clone()))));
+                let val = self.get_val_num(value)?;
+                self.innermost().num_var.insert(name.to_string(), val);
             }
             BaseType::STRING => {
-                self.str_var.extend(iter::once((root.identity.clone(), self.get_val_str(root.l_children[0].clone()))));
+                let val = self.get_val_str(value)?;
+                self.innermost().str_var.insert(name.to_string(), val);
+            }
+            BaseType::LIST(item) => {
+                let val = Value::LIST((**item).clone(), self.get_val_list(value)?);
+                self.innermost().list_var.insert(name.to_string(), val);
+            }
+            BaseType::MAP(item) => {
+                let val = Value::MAP((**item).clone(), self.get_val_map(value)?);
+                self.innermost().map_var.insert(name.to_string(), val);
             }
             BaseType::VOID => {
-                panic!("ERROR: void type");
+                return Err(self.err("E0406", format!("void type in declaration of {}", name), span).into());
             }
         }
+        Ok(())
     }
 
-    pub fn run_node(&mut self, n: Node) {
-        match n.node_type {
-            NodeType::EOL | NodeType::EOF => {
-                return;
+    /// NAME = value; for a variable of any type
+    fn change_var(&mut self, name: &str, value: &Expr, span: Span) -> Result<(), Box<Diagnostic>> {
+        match value.val_type() {
+            BaseType::NUMBER => {
+                let val = self.get_val_num(value)?;
+                self.replace_num_var(name, val, span)?;
+            }
+            BaseType::STRING => {
+                let val = self.get_val_str(value)?;
+                self.replace_str_var(name, val, span)?;
             }
-            NodeType::DECLARE => {
-                self.reg_var(n);
+            BaseType::LIST(_) => {
+                let val = self.get_val_list(value)?;
+                self.replace_list_var(name, val, span)?;
             }
-            NodeType::PUT => {
-                match n.l_children[0].val_type {
-                    BaseType::NUMBER => {
-                        println!("{}", self.get_val_num(n.l_children[0].clone()));
-                    }
-                    BaseType::STRING => {
-                        println!("{}", self.get_val_str(n.l_children[0].clone()));
-                    }
-                    BaseType::VOID => {
-                        panic!("ERROR: void type in put");
-                    }
+            BaseType::MAP(_) => {
+                let val = self.get_val_map(value)?;
+                self.replace_map_var(name, val, span)?;
+            }
+            BaseType::VOID => {
+                return Err(self.err("E0406", String::from("void type in change"), span).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// list[index] = value; or map[key] = value;
+    fn set_item(&mut self, target: &Expr, value: &Expr) -> Result<(), Box<Diagnostic>> {
+        let val = self.get_val(value)?;
+        if let Expr::INDEX { list, index, .. } = target {
+            let key = self.get_val(index)?;
+            match (self.place(list)?, key) {
+                //a map takes new keys, a list index has to be there already
+                (Value::MAP(_, entries), Value::STRING(key)) => {
+                    entries.insert(key, val);
+                }
+                (container, key) => {
+                    *item_of(container, &key, index.span())? = val;
                 }
             }
-            NodeType::CHANGE => {
-                match n.l_children[0].val_type {
+        }
+        Ok(())
+    }
+
+    /// keeps the value of a RET in the frame of the running CALL
+    fn set_ret(&mut self, value: &Expr, span: Span) -> Result<(), Box<Diagnostic>> {
+        match value.val_type() {
+            BaseType::NUMBER => {
+                let val = self.get_val_num(value)?;
+                self.frames.last_mut().unwrap().ret_num = val;
+            }
+            BaseType::STRING => {
+                let val = self.get_val_str(value)?;
+                self.frames.last_mut().unwrap().ret_str = val;
+            }
+            BaseType::LIST(_) => {
+                let val = self.get_val_list(value)?;
+                self.frames.last_mut().unwrap().ret_list = val;
+            }
+            BaseType::MAP(_) => {
+                let val = self.get_val_map(value)?;
+                self.frames.last_mut().unwrap().ret_map = val;
+            }
+            BaseType::VOID => {
+                return Err(self.err("E0406", String::from("RET with a value in a function without a return type"), span).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// every block gets its own scope, dropped however the block ends
+    fn run_block(&mut self, stmts: &[Stmt]) -> Result<Flow, Box<Diagnostic>> {
+        let span = stmts.first().map(|stmt| stmt.span()).unwrap_or_default();
+        self.nested(span, |parser| {
+            parser.frames.last_mut().unwrap().scopes.push(Scope::default());
+            let flow = parser.run_stmts(stmts);
+            parser.frames.last_mut().unwrap().scopes.pop();
+            flow
+        })
+    }
+
+    /// a bound or the STEP of a FOR, NaN or infinity would keep it from ever ending
+    fn for_bound(&mut self, expr: &Expr) -> Result<f64, Box<Diagnostic>> {
+        let val = self.get_val_num(expr)?;
+        if !val.is_finite() {
+            return Err(self.err("E0407", format!("{} in a FOR, the loop would never end", val), expr.span()).into());
+        }
+        Ok(val)
+    }
+
+    /// counts var to to by step, the body can change var and the next round goes on from there
+    /// a var the body made NaN ends the loop too
+    fn run_for(&mut self, var: &str, to: f64, step: f64, body: &[Stmt], label: &Option<String>) -> Result<Flow, Box<Diagnostic>> {
+        loop {
+            let val = self.innermost().num_var[var];
+            if val.is_nan() || (step > 0.00 && val > to) || (step < 0.00 && val < to) {
+                return Ok(Flow::Next);
+            }
+            if let Some(flow) = after_body(self.run_block(body)?, label) {
+                return Ok(flow);
+            }
+            *self.innermost().num_var.get_mut(var).unwrap() += step;
+        }
+    }
+
+    fn run_stmts(&mut self, stmts: &[Stmt]) -> Result<Flow, Box<Diagnostic>> {
+        for each in stmts {
+            match self.run_stmt(each)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn run_stmt(&mut self, stmt: &Stmt) -> Result<Flow, Box<Diagnostic>> {
+        match stmt {
+            Stmt::EMPTY { .. } | Stmt::EOF => {}
+            Stmt::DECLARE { name, val_type, value, span } => {
+                self.reg_var(name, val_type, value, *span)?;
+            }
+            Stmt::PUT { value, span } => {
+                match value.val_type() {
                     BaseType::NUMBER => {
-                        self.replace_num_var(&n.identity, self.get_val_num(n.l_children[0].clone()));
+                        println!("{}", self.get_val_num(value)?);
                     }
                     BaseType::STRING => {
-                        self.replace_str_var(&n.identity, self.get_val_str(n.l_children[0].clone()));
+                        println!("{}", self.get_val_str(value)?);
+                    }
+                    BaseType::LIST(_) | BaseType::MAP(_) => {
+                        println!("{}", self.get_val(value)?);
                     }
                     BaseType::VOID => {
-                        panic!("ERROR: void type in change");
+                        return Err(self.err("E0406", String::from("void type in put"), *span).into());
                     }
                 }
             }
-            NodeType::IF => {
-                let cond = self.get_val_num(n.extra[0].clone());
-                if cond != 0.00 {
-                    for each in n.l_children {
-                        self.run_node(each.clone());
-                    }
-                }
-                else{
-                    for each in n.r_children {
-                        self.run_node(each.clone());
+            Stmt::CHANGE { name, value, span } => {
+                self.change_var(name, value, *span)?;
+            }
+            Stmt::IF { branches, else_block, .. } => {
+                for branch in branches {
+                    if self.get_val_num(&branch.cond)? != 0.00 {
+                        return self.run_block(&branch.body);
                     }
                 }
+                return self.run_block(else_block);
             }
-            NodeType::WHILE => {
+            Stmt::WHILE { cond, body, label, .. } => {
                 loop{
-                    let cond = self.get_val_num(n.extra[0].clone());
-                    if cond == 0.00 {
+                    let val = self.get_val_num(cond)?;
+                    if val == 0.00 {
                         break;
                     }
-                    else{
-                        for each in n.l_children.clone() {
-                            self.run_node(each.clone());
+                    if let Some(flow) = after_body(self.run_block(body)?, label) {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::FOR { var, from, to, step, body, label, .. } => {
+                let from = self.for_bound(from)?;
+                let to = self.for_bound(to)?;
+                let step = match step {
+                    Some(step) => {
+                        let val = self.for_bound(step)?;
+                        if val == 0.00 {
+                            return Err(self.err("E0407", String::from("STEP of a FOR is 0, the loop would never end"), step.span())
+                                .with_help(String::from("use a WHILE for a loop that only ends with BREAK")).into());
                         }
+                        val
                     }
+                    None => 1.00,
+                };
+                //the loop variable lives in a scope of its own around the body
+                let mut scope = Scope::default();
+                scope.num_var.insert(var.clone(), from);
+                self.frames.last_mut().unwrap().scopes.push(scope);
+                let flow = self.run_for(var, to, step, body, label);
+                self.frames.last_mut().unwrap().scopes.pop();
+                return flow;
+            }
+            Stmt::BREAK { label, .. } => {
+                return Ok(Flow::Break(label.clone()));
+            }
+            Stmt::CONTINUE { label, .. } => {
+                return Ok(Flow::Continue(label.clone()));
+            }
+            Stmt::FUNCTION(func) => {
+                self.functions.insert(func.name.clone(), Rc::new(func.clone()));
+            }
+            Stmt::CALL(call) => {
+                self.call(call)?;
+            }
+            Stmt::BUILTIN(call) => {
+                self.builtin(call)?;
+            }
+            Stmt::SET { target, value, .. } => {
+                self.set_item(target, value)?;
+            }
+            Stmt::RET { value, span } => {
+                if let Some(value) = value {
+                    self.set_ret(value, *span)?;
                 }
+                return Ok(Flow::Ret);
             }
-            _ => {
+        }
+        Ok(Flow::Next)
+    }
+
+    /// reads every statement in text, the prelude counts as declared
+    pub fn parse(&self, text: TextStream) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+        let mut in_stream = ASTStream::new(TokenStream::new(text).map_err(|e| vec![*e])?, &self.prelude())?;
+        let mut program = vec![];
+        while !in_stream.eof() {
+            let stmt = match in_stream.next() {
+                Ok(stmt) => stmt,
+                Err(e) => return Err(in_stream.with_lex_errors(*e)),
+            };
+            match stmt {
+                Stmt::EMPTY { .. } => {
 
+                }
+                stmt => {
+                    program.push(stmt);
+                }
             }
         }
+        Ok(program)
     }
 
-    pub fn run(&mut self) {
-        while !self.in_stream.eof() {
-            let tmp = self.in_stream.next();
-            match tmp.node_type {
-                NodeType::EOL => {
+    /// text as one expression, the prelude counts as declared
+    pub fn parse_expression(&self, text: TextStream) -> Result<Expr, Vec<Diagnostic>> {
+        ASTStream::parse_lone_expression(TokenStream::new(text).map_err(|e| vec![*e])?, &self.prelude())
+    }
 
+    pub fn type_of(&self, expr: &Expr) -> Result<BaseType, Vec<Diagnostic>> {
+        TypeChecker::new(&self.prelude()).check_expr(expr)
+    }
+
+    /// the value of an expression at the top level, None for a CALL that returns nothing
+    pub fn eval(&mut self, expr: &Expr) -> Result<Option<Value>, Box<Diagnostic>> {
+        match expr.val_type() {
+            BaseType::NUMBER => Ok(Some(Value::NUMBER(self.get_val_num(expr)?))),
+            BaseType::STRING => Ok(Some(Value::STRING(self.get_val_str(expr)?))),
+            BaseType::LIST(item) => Ok(Some(Value::LIST(*item, self.get_val_list(expr)?))),
+            BaseType::MAP(item) => Ok(Some(Value::MAP(*item, self.get_val_map(expr)?))),
+            BaseType::VOID => {
+                match expr {
+                    Expr::CALL(call) => {
+                        self.call(call)?;
+                    }
+                    Expr::BUILTIN(call) => {
+                        self.builtin(call)?;
+                    }
+                    _ => {}
                 }
-                _ => {
-                    self.run_node(tmp);
-                }
+                Ok(None)
             }
         }
     }
-}
\ No newline at end of file
+
+    pub fn check(&self, program: &[Stmt]) -> Vec<Diagnostic> {
+        TypeChecker::new(&self.prelude()).check(program)
+    }
+
+    /// nothing runs unless the whole program parses and type checks
+    pub fn run(&mut self, text: TextStream) -> Result<(), Vec<Diagnostic>> {
+        let program = self.parse(text)?;
+        let errors = self.check(&program);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        for each in &program {
+            self.run_stmt(each).map_err(|e| vec![*e])?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::iter;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::{diagnostic::{Diagnostic, Severity}, util::Span};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const CYAN: &str = "\x1b[1;36m";
+const BLUE: &str = "\x1b[1;34m";
+
+//a span longer than this only shows its first and last rows
+const MAX_SNIPPET_ROWS: usize = 4;
+
+/// prints diagnostics like rustc, the source rows of the span with ^^^ under it
+pub struct Renderer{
+    rows: Vec<String>,
+    name: String,
+    color: bool
+}
+
+impl Renderer{
+    /// content is the source the spans point into, name is what the --> line calls it
+    pub fn new(content: &[u8], name: &str, color: bool) -> Renderer{
+        let rows = String::from_utf8_lossy(content)
+            .split('\n')
+            .map(|row| row.trim_end_matches('\r').to_string())
+            .collect();
+        Renderer { rows, name: name.to_string(), color }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String{
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        }
+        else {
+            text.to_string()
+        }
+    }
+
+    fn severity_style(severity: Severity) -> &'static str{
+        match severity {
+            Severity::ERROR => RED,
+            Severity::WARNING => YELLOW,
+            Severity::NOTE => GREEN,
+            Severity::HELP => CYAN,
+        }
+    }
+
+    fn header(&self, diagnostic: &Diagnostic) -> String{
+        let mut kind = diagnostic.severity.to_string();
+        if !diagnostic.code.is_empty() {
+            kind = format!("{}[{}]", kind, diagnostic.code);
+        }
+        format!("{}{}\n", self.paint(Renderer::severity_style(diagnostic.severity), &kind), self.paint(BOLD, &format!(": {}", diagnostic.message)))
+    }
+
+    /// the --> line, the rows the span covers and the ^^^ under each of them
+    fn snippet(&self, span: Span, severity: Severity, width: usize) -> String{
+        let gutter = self.paint(BLUE, &format!("{} |", " ".repeat(width)));
+        let mut out = format!("{}{} {}:{}:{}\n", " ".repeat(width), self.paint(BLUE, "-->"), self.name, span.row, span.col);
+        out += &format!("{}\n", gutter);
+        let last = span.end_row.max(span.row);
+        for row in span.row..=last {
+            if last - span.row >= MAX_SNIPPET_ROWS && row > span.row + 1 && row < last {
+                if row == span.row + 2 {
+                    out += &format!("{}\n", self.paint(BLUE, "..."));
+                }
+                continue;
+            }
+            let text = self.rows.get(row - 1).map(String::as_str).unwrap_or("");
+            let start = if row == span.row {
+                span.col
+            }
+            else {
+                text.chars().take_while(|ch| ch.is_whitespace()).count() + 1
+            };
+            let end = if row == span.end_row { span.end_col } else { text.chars().count() + 1 };
+            //keep tabs and count wide chars twice so the carets line up with the row above
+            let indent: String = text.chars().take(start.saturating_sub(1)).map(|ch| if ch == '\t' { String::from("\t") } else { " ".repeat(width_of(ch)) }).collect();
+            //a col past the end of the row is one caret wide
+            let spanned: usize = text.chars()
+                .chain(iter::repeat(' '))
+                .skip(start.saturating_sub(1))
+                .take(end.saturating_sub(start))
+                .map(width_of)
+                .sum();
+            let carets = "^".repeat(spanned.max(1));
+            out += &format!("{} {}\n", self.paint(BLUE, &format!("{:>width$} |", row, width = width)), text);
+            out += &format!("{} {}{}\n", gutter, indent, self.paint(Renderer::severity_style(severity), &carets));
+        }
+        out
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String{
+        //the gutter is as wide as the largest row number shown
+        let width = iter_spans(diagnostic).map(|span| span.end_row.max(span.row).to_string().len()).max().unwrap_or(1);
+        let mut out = self.header(diagnostic);
+        if let Some(span) = diagnostic.span {
+            out += &self.snippet(span, diagnostic.severity, width);
+        }
+        for child in &diagnostic.children {
+            match child.span {
+                Some(span) => {
+                    out += &self.header(child);
+                    out += &self.snippet(span, child.severity, width);
+                }
+                None => {
+                    let kind = self.paint(BOLD, &format!("= {}", child.severity));
+                    out += &format!("{}{}: {}\n", " ".repeat(width + 1), kind, child.message);
+                }
+            }
+        }
+        out
+    }
+}
+
+//control chars have no width of their own, the terminal still shows something for them
+fn width_of(ch: char) -> usize{
+    ch.width().unwrap_or(1)
+}
+
+fn iter_spans(diagnostic: &Diagnostic) -> impl Iterator<Item = Span> + '_{
+    diagnostic.span.into_iter().chain(diagnostic.children.iter().filter_map(|child| child.span))
+}
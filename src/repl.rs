@@ -0,0 +1,200 @@
+use std::{env, io::{self, stderr, BufRead, IsTerminal, Write}};
+
+use double_basic::{token_stream::tokenize, util::TokenType, Interpreter, RunError, TextStream, Value};
+
+const HELP: &str = "\
+statements run as soon as they are complete, a bare expression prints its value
+a line that leaves a { open, ends with } or leaves a statement without its ; waits for more,
+an empty line sends what is there
+
+:vars           list the globals and their values
+:type expr      print the type of expr
+:ast expr       print the syntax tree of expr
+:reset          forget every global and function
+:load file      run a file here, what it declares stays
+:help           print this help
+:quit           leave, so does the end of input
+";
+
+//what the --> line of an error calls what was typed
+const NAME: &str = "<repl>";
+
+/// false while a { is still open, after a } that could be followed by ELSE or wants its ;, or while a statement has no ; yet
+fn is_complete(source: &str) -> bool {
+    let tokens = match tokenize(TextStream::from(source)) {
+        Ok(tokens) => tokens,
+        //a /* still open waits for its */
+        Err(errors) if errors.iter().any(|e| e.code == "E0102") => return false,
+        //running it reports the error
+        Err(_) => return true,
+    };
+    let depth: i32 = tokens.iter()
+        .map(|token| match token.token_type {
+            TokenType::BEGIN => 1,
+            TokenType::END => -1,
+            _ => 0,
+        })
+        .sum();
+    if depth > 0 {
+        return false;
+    }
+    match tokens.last().map(|token| token.token_type) {
+        Some(TokenType::END) => false,
+        //Y: NUMBER = 1 + goes on in the next line, a bare expression has no ; to wait for
+        Some(TokenType::SEMICOLON) | None => true,
+        Some(_) => !is_statement(source),
+    }
+}
+
+/// statements start with a keyword or with `name :`, `name =` or `name[index] =`, anything else is an expression to print
+fn is_statement(source: &str) -> bool {
+    let tokens = tokenize(TextStream::from(source)).unwrap_or_default();
+    match (tokens.first().map(|token| token.token_type), tokens.get(1).map(|token| token.token_type)) {
+        //A[0] = 1 sets an item, A[0] prints it
+        (Some(TokenType::VAR), Some(TokenType::LSQUARE)) => tokens.iter().any(|token| token.token_type == TokenType::ASSIGN),
+        pair => matches!(
+            pair,
+            //only comments, nothing to print
+            (None, _)
+                | (Some(TokenType::VAR), Some(TokenType::COLON | TokenType::ASSIGN))
+                | (Some(TokenType::PUT | TokenType::IF | TokenType::WHILE | TokenType::FOR | TokenType::FUNCTION | TokenType::RET | TokenType::BREAK | TokenType::CONTINUE | TokenType::SEMICOLON), _)
+        ),
+    }
+}
+
+/// strings are quoted so "1" and 1 look different
+fn show(value: &Value) -> String {
+    match value {
+        Value::NUMBER(val) => val.to_string(),
+        Value::STRING(val) => format!("{:?}", val),
+        //the items of a list or map are already quoted
+        Value::LIST(..) | Value::MAP(..) => value.to_string(),
+    }
+}
+
+struct Repl {
+    interpreter: Interpreter,
+    color: bool
+}
+
+impl Repl {
+    fn report<T>(&self, result: Result<T, RunError>) -> Option<T> {
+        match result {
+            Ok(val) => Some(val),
+            Err(e) => {
+                eprint!("{}", e.render(self.color));
+                None
+            }
+        }
+    }
+
+    fn submit(&mut self, source: &str) {
+        let text = TextStream::from(source);
+        if is_statement(source) {
+            let result = self.interpreter.run_text(text, NAME);
+            self.report(result);
+        }
+        else {
+            let result = self.interpreter.eval_text(text, NAME);
+            if let Some(Some(value)) = self.report(result) {
+                println!("{}", show(&value));
+            }
+        }
+    }
+
+    /// a : command, false when it is time to leave
+    fn meta(&mut self, line: &str) -> bool {
+        let (command, arg) = match line.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (line, ""),
+        };
+        match (command, arg) {
+            ("vars", "") => {
+                for (name, value) in self.interpreter.globals() {
+                    println!("{}: {:?} = {}", name, value.val_type(), show(&value));
+                }
+            }
+            ("type", expr) if !expr.is_empty() => {
+                let result = self.interpreter.type_of_text(TextStream::from(expr), NAME);
+                if let Some(val_type) = self.report(result) {
+                    println!("{:?}", val_type);
+                }
+            }
+            ("ast", expr) if !expr.is_empty() => {
+                let result = self.interpreter.parse_expression_text(TextStream::from(expr), NAME);
+                if let Some(expr) = self.report(result) {
+                    println!("{:#?}", expr);
+                }
+            }
+            ("reset", "") => {
+                self.interpreter = Interpreter::new();
+            }
+            ("load", path) if !path.is_empty() => {
+                let result = self.interpreter.run_file(path);
+                self.report(result);
+            }
+            ("help", "") => {
+                print!("{}", HELP);
+            }
+            ("quit", "") => {
+                return false;
+            }
+            ("type" | "ast" | "load", _) => {
+                eprintln!("error: :{} needs an argument, :help lists the commands", command);
+            }
+            _ => {
+                eprintln!("error: unknown command :{}, :help lists the commands", line);
+            }
+        }
+        true
+    }
+}
+
+/// reads stdin until it ends or :quit, nothing typed here can end the session with an error
+pub fn run() {
+    let stdin = io::stdin();
+    //prompts would only clutter piped output
+    let interactive = stdin.is_terminal();
+    let mut repl = Repl {
+        interpreter: Interpreter::new(),
+        color: stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+    };
+    let mut buf = String::new();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("{}", if buf.is_empty() { "> " } else { "... " });
+            io::stdout().flush().unwrap();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if buf.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(command) = line.trim().strip_prefix(':') {
+                if !repl.meta(command) {
+                    return;
+                }
+                continue;
+            }
+        }
+        if !line.trim().is_empty() {
+            buf.push_str(&line);
+            buf.push('\n');
+            if !is_complete(&buf) {
+                continue;
+            }
+        }
+        repl.submit(&buf);
+        buf.clear();
+    }
+    if !buf.is_empty() {
+        repl.submit(&buf);
+    }
+    if interactive {
+        println!();
+    }
+}
@@ -1,67 +1,147 @@
 use std::{fs::File, io::Read};
 
-use crate::util::Stream;
+use crate::{diagnostic::Diagnostic, util::{Span, Stream}};
 
 
 pub struct TextStream{
-    //the pos here is the next pos
+    //the pos here is the index in chars of next
     pos: usize,
+    //row and col of cur, both count from 1, col counts chars
     row: usize,
     col: usize,
     content: Vec<u8>,
-    cur: u8,
-    next: u8
+    //each char of content and the byte it starts at, up to the first invalid utf-8
+    chars: Vec<(usize, char)>,
+    //the byte where content stops being utf-8, reading up to it is an error
+    invalid: Option<usize>,
+    cur: char,
+    next: char
 }
 
 impl TextStream{
-    pub fn new(path: &str) -> TextStream{
-        let mut f = File::open(path).unwrap();
+    /// reads the file at path
+    pub fn new(path: &str) -> Result<TextStream, Box<Diagnostic>>{
+        let file = File::open(path).map_err(|e| Diagnostic::error("E0001", format!("cannot read {}: {}", path, e), None))?;
+        TextStream::from_reader(file, path)
+    }
+
+    /// reads everything up to the end of reader, name is what the error calls it
+    pub fn from_reader<R: Read>(mut reader: R, name: &str) -> Result<TextStream, Box<Diagnostic>>{
         let mut buf: Vec<u8> = vec![];
-        f.read_to_end(&mut buf).unwrap();
-        let first = buf[0];
-        TextStream { pos: 0, row: 1, col: 1, content: buf, cur: 0, next: first}
+        reader.read_to_end(&mut buf)
+            .map_err(|e| Diagnostic::error("E0001", format!("cannot read {}: {}", name, e), None))?;
+        Ok(TextStream::from_bytes(buf))
     }
-}
 
-impl Stream<u8> for TextStream{
-    fn next(&mut self) -> u8{
-        self.pos += 1;
-        self.col += 1;
-        if self.cur == '\n' as u8 {
-            self.col = 0;
-            self.row += 1;
+    /// an empty source is a program that does nothing, invalid utf-8 is only an error once it is read
+    pub fn from_bytes(content: Vec<u8>) -> TextStream{
+        let (valid, invalid) = match std::str::from_utf8(&content) {
+            Ok(valid) => (valid, None),
+            Err(e) => (std::str::from_utf8(&content[..e.valid_up_to()]).unwrap(), Some(e.valid_up_to())),
+        };
+        let chars = valid.char_indices().collect();
+        let mut stream = TextStream { pos: 0, row: 1, col: 0, content: vec![], chars, invalid, cur: '\0', next: '\0' };
+        stream.next = stream.char_at(0);
+        stream.content = content;
+        stream
+    }
+
+    //'\0' past the end, and a stand in for the invalid byte that stops the stream
+    fn char_at(&self, index: usize) -> char{
+        match self.chars.get(index) {
+            Some(&(_, ch)) => ch,
+            None if index == self.chars.len() && self.invalid.is_some() => char::REPLACEMENT_CHARACTER,
+            None => '\0',
         }
-        self.cur = self.next;
-        if self.pos < self.content.len() {
-            self.next = self.content[self.pos];
+    }
+
+    //the byte the char at index starts at, the end of the valid utf-8 past the last char
+    fn byte_at(&self, index: usize) -> usize{
+        match self.chars.get(index) {
+            Some(&(byte, _)) => byte,
+            None => self.invalid.unwrap_or(self.content.len()),
         }
-        else{
-            self.next = 0;
+    }
+
+    /// the whole source, for rendering diagnostics against
+    pub fn content(&self) -> &[u8]{
+        &self.content
+    }
+
+    /// the char n places after peek, '\0' past the end
+    pub fn peek_at(&self, n: usize) -> char{
+        self.char_at(self.pos + n)
+    }
+
+    /// true once cur has gone past the last char, unlike eof a '\0' in the source is not the end
+    pub fn past_end(&self) -> bool{
+        self.pos > self.chars.len()
+    }
+
+    /// span of the cur char alone, tokens are built by joining the marks of their first and last char
+    pub fn mark(&self) -> Span{
+        let index = self.pos.saturating_sub(1);
+        let start = if self.pos == 0 { 0 } else { self.byte_at(index) };
+        //past the end the mark is empty
+        let (end, width) = match self.chars.get(index) {
+            Some(&(_, ch)) if self.pos > 0 => (start + ch.len_utf8(), 1),
+            _ => (start, 0),
+        };
+        Span { start, end, row: self.row, col: self.col, end_row: self.row, end_col: self.col + width }
+    }
+}
+
+impl From<&str> for TextStream{
+    fn from(source: &str) -> TextStream{
+        TextStream::from_bytes(source.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for TextStream{
+    fn from(source: String) -> TextStream{
+        TextStream::from_bytes(source.into_bytes())
+    }
+}
+
+impl Stream<char> for TextStream{
+    fn next(&mut self) -> Result<char, Box<Diagnostic>>{
+        let (row, col) = if self.cur == '\n' { (self.row + 1, 1) } else { (self.row, self.col + 1) };
+        if let (true, Some(start)) = (self.pos == self.chars.len(), self.invalid) {
+            let span = Span { start, end: start + 1, row, col, end_row: row, end_col: col + 1 };
+            return Err(Diagnostic::error("E0101", String::from("invalid utf-8 in source"), Some(span))
+                .with_help(String::from("the source has to be utf-8 text")).into());
         }
-        self.cur
+        self.pos += 1;
+        self.row = row;
+        self.col = col;
+        self.cur = self.next;
+        self.next = self.char_at(self.pos);
+        Ok(self.cur)
     }
 
-    fn peek(&self) -> u8{
+    fn peek(&self) -> char{
         self.next
     }
 
-    fn current(&self) -> u8{
+    fn current(&self) -> char{
         self.cur
     }
 
-    fn reset(&mut self){
+    fn reset(&mut self) -> Result<(), Box<Diagnostic>>{
         self.pos = 0;
         self.row = 1;
-        self.col = 1;
-        self.cur = 0;
-        self.next = self.content[self.pos];
+        self.col = 0;
+        self.cur = '\0';
+        self.next = self.char_at(0);
+        Ok(())
     }
 
+    //nothing left after cur, a pending invalid byte is still something to read
     fn eof(&self) -> bool{
-        self.next == 0
+        self.pos >= self.chars.len() && (self.invalid.is_none() || self.pos > self.chars.len())
     }
 
-    fn err(&self, msg: &str) {
-        panic!("{} row: {} col: {}", msg, self.row, self.col);
+    fn err(&self, code: &'static str, msg: &str) -> Diagnostic {
+        Diagnostic::error(code, msg.to_string(), Some(self.mark()))
     }
-}
\ No newline at end of file
+}
@@ -1,80 +1,279 @@
-use crate::{util::{TokenType, Stream}, text_stream::TextStream};
+use unicode_xid::UnicodeXID;
+
+use crate::{diagnostic::Diagnostic, util::{Span, TokenType, Stream}, text_stream::TextStream};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind{
+    //a # or // comment, up to the end of the line
+    LINE,
+    //a /* */ comment, may hold other block comments
+    BLOCK
+}
+
+/// a comment the lexer skipped, text keeps its markers
+#[derive(Debug, Clone)]
+pub struct Trivia{
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
 
 #[derive(Debug)]
 pub struct Token{
     pub token_type: TokenType,
     pub value: String,
+    pub span: Span,
+    //the comments between the previous token and this one, the EOF token holds the ones at the end
+    pub trivia: Vec<Trivia>,
 }
 
 impl Token{
     pub fn new(token_type: TokenType, value: String) -> Token{
-        Token { token_type, value }
+        Token { token_type, value, span: Span::default(), trivia: vec![] }
     }
     pub fn new_eof() -> Token{
-        Token { token_type: TokenType::EOF, value: String::from("") }
+        Token { token_type: TokenType::EOF, value: String::from(""), span: Span::default(), trivia: vec![] }
     }
 }
 
 impl Clone for Token{
     fn clone(&self) -> Token {
-        Token { token_type: self.token_type.clone(), value: self.value.clone() }
+        Token { token_type: self.token_type, value: self.value.clone(), span: self.span, trivia: self.trivia.clone() }
     }
 }
 pub struct TokenStream{
     in_stream: TextStream,
     cur: Token,
     next: Token,
+    //one for each ERROR token lexed so far
+    errors: Vec<Diagnostic>,
 }
 
 
 impl TokenStream{
-    fn read_until_f(&mut self, test: fn(ch: u8) -> bool, cur: u8) -> String{
-        let mut buf: Vec<u8> = vec![];
-        buf.push(cur);
-        let mut ret: Vec<u8> = vec![];
-        while !self.in_stream.eof(){
-            if test(self.in_stream.peek()) {
-                buf.push(self.in_stream.next());
-            }    
-            else{
-                ret = buf;
-                break;
+    //the end of the source ends the word too, so a program may stop right after its last token
+    fn read_until_f(&mut self, test: fn(ch: char) -> bool, cur: char) -> Result<String, Box<Diagnostic>>{
+        let mut buf = String::from(cur);
+        while !self.in_stream.eof() && test(self.in_stream.peek()) {
+            buf.push(self.in_stream.next()?);
+        }
+        Ok(buf)
+    }
+
+    //cur is the / of the opening /*, start is its mark
+    fn read_block_comment(&mut self, start: Span) -> Result<String, Box<Diagnostic>>{
+        let mut buf = String::from('/');
+        buf.push(self.in_stream.next()?);
+        let open = start.to(self.in_stream.mark());
+        let mut depth = 1;
+        while depth > 0 {
+            if self.in_stream.eof() {
+                return Err(Diagnostic::error("E0102", String::from("unterminated block comment"), Some(open))
+                    .with_note(String::from("the source ends before its */"), Some(self.in_stream.mark()))
+                    .with_help(String::from("every /* needs a matching */, nested ones too")).into());
+            }
+            let ch = self.in_stream.next()?;
+            buf.push(ch);
+            if (ch == '/' && self.in_stream.peek() == '*') || (ch == '*' && self.in_stream.peek() == '/') {
+                buf.push(self.in_stream.next()?);
+                depth += if ch == '/' { 1 } else { -1 };
             }
         }
-        String::from_utf8(ret).unwrap()
+        Ok(buf)
     }
-    fn read_until(&mut self, test: fn(ch: u8) -> bool) -> String{
-        let mut buf: Vec<u8> = vec![];
-        let mut ret: Vec<u8> = vec![];
-        while !self.in_stream.eof(){
-            if test(self.in_stream.peek()) {
-                buf.push(self.in_stream.next());
-            }    
-            else{
-                ret = buf;
+
+    fn unterminated_string(&self, open: Span, help: String) -> Diagnostic{
+        Diagnostic::error("E0103", String::from("unterminated string"), Some(open))
+            .with_note(String::from("the source ends before its closing \""), Some(self.in_stream.mark()))
+            .with_help(help)
+    }
+
+    //cur is the \ and start its mark
+    fn read_escape(&mut self, start: Span, buf: &mut String) -> Result<(), Box<Diagnostic>>{
+        let ch = self.in_stream.next()?;
+        let val = match ch {
+            '"' => '"',
+            '\\' => '\\',
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'u' => return self.read_unicode_escape(start, buf),
+            _ => {
+                let msg = if ch.is_control() { String::from("unknown escape") } else { format!("unknown escape \\{}", ch) };
+                return Err(Diagnostic::error("E0104", msg, Some(start.to(self.in_stream.mark())))
+                    .with_help(String::from("the escapes are \\\" \\\\ \\n \\t \\r and \\u{...}, a raw string r\"...\" keeps every \\ as it is")).into());
+            }
+        };
+        buf.push(val);
+        Ok(())
+    }
+
+    //cur is the u of \u{...}
+    fn read_unicode_escape(&mut self, start: Span, buf: &mut String) -> Result<(), Box<Diagnostic>>{
+        let mut digits = String::new();
+        let mut closed = false;
+        if self.in_stream.peek() == '{' {
+            self.in_stream.next()?;
+            while self.in_stream.peek().is_ascii_hexdigit() && digits.len() < 6 {
+                digits.push(self.in_stream.next()?);
+            }
+            if self.in_stream.peek() == '}' {
+                self.in_stream.next()?;
+                closed = true;
+            }
+        }
+        let span = Some(start.to(self.in_stream.mark()));
+        if !closed || digits.is_empty() {
+            return Err(Diagnostic::error("E0105", String::from("malformed unicode escape"), span)
+                .with_help(String::from("write it as \\u{...} with 1 to 6 hex digits, like \\u{1F600}")).into());
+        }
+        match char::from_u32(u32::from_str_radix(&digits, 16).unwrap()) {
+            Some(val) => buf.push(val),
+            None => return Err(Diagnostic::error("E0105", format!("\\u{{{}}} is not a unicode character", digits), span).into()),
+        }
+        Ok(())
+    }
+
+    //cur is the opening ", start is its mark
+    fn read_string(&mut self, start: Span) -> Result<String, Box<Diagnostic>>{
+        let mut buf = String::new();
+        loop {
+            if self.in_stream.eof() {
+                return Err(self.unterminated_string(start, String::from("close it with \", or write \\\" for a \" inside it")).into());
+            }
+            let ch = self.in_stream.next()?;
+            match ch {
+                '"' => break,
+                '\\' => {
+                    let escape = self.in_stream.mark();
+                    if self.in_stream.eof() {
+                        return Err(self.unterminated_string(start, String::from("close it with \", or write \\\" for a \" inside it")).into());
+                    }
+                    self.read_escape(escape, &mut buf)?;
+                }
+                _ => buf.push(ch),
+            }
+        }
+        Ok(buf)
+    }
+
+    /// r"..." or r#"..."# with any number of #, the cur r is followed by them
+    fn is_raw_string(&self) -> bool{
+        let mut n = 0;
+        while self.in_stream.peek_at(n) == '#' {
+            n += 1;
+        }
+        self.in_stream.peek_at(n) == '"'
+    }
+
+    //nothing is escaped, the string ends at the first " followed by as many # as it began with
+    fn read_raw_string(&mut self, start: Span) -> Result<String, Box<Diagnostic>>{
+        let mut hashes = 0;
+        while self.in_stream.next()? == '#' {
+            hashes += 1;
+        }
+        let open = start.to(self.in_stream.mark());
+        let mut buf = String::new();
+        loop {
+            if self.in_stream.eof() {
+                let close = format!("\"{}", "#".repeat(hashes));
+                return Err(self.unterminated_string(open, format!("this raw string ends at the first {}", close)).into());
+            }
+            let ch = self.in_stream.next()?;
+            if ch == '"' && (0..hashes).all(|n| self.in_stream.peek_at(n) == '#') {
+                for _ in 0..hashes {
+                    self.in_stream.next()?;
+                }
                 break;
             }
+            buf.push(ch);
         }
-        String::from_utf8(ret).unwrap()
+        Ok(buf)
     }
 
-    pub fn prase_next(&mut self) -> Token{
-        let mut ch = self.in_stream.next();
-        while ch == ' ' as u8 || ch == '\n' as u8 || ch == '\t' as u8{
-            ch = self.in_stream.next();
+    //keeps the diagnostic for the caller and lexes on
+    fn error_token(&mut self, diagnostic: Diagnostic, text: String, span: Span) -> Token{
+        self.errors.push(diagnostic);
+        let mut token = Token::new(TokenType::ERROR, text);
+        token.span = span;
+        token
+    }
+
+    fn unknown_char(&mut self, ch: char, span: Span) -> Token{
+        let mut diagnostic = Diagnostic::error("E0106", format!("unknown character {:?}", ch), Some(span));
+        if ch == '&' {
+            diagnostic = diagnostic.with_help(String::from("the logical and is &&"));
+        }
+        else if ch.is_xid_continue() {
+            diagnostic = diagnostic.with_help(String::from("a name has to start with a letter"));
         }
+        self.error_token(diagnostic, String::from(ch), span)
+    }
+
+    //the whole literal, bad digits and extra dots too, so it is reported once
+    fn read_number(&mut self, first: char) -> Result<String, Box<Diagnostic>>{
+        let mut buf = String::from(first);
+        loop {
+            let ch = self.in_stream.peek();
+            let hex = buf.starts_with("0x") || buf.starts_with("0X");
+            let sign = (ch == '+' || ch == '-') && buf.ends_with(['e', 'E']) && !hex;
+            if !(ch.is_xid_continue() || ch == '.' || sign) {
+                return Ok(buf);
+            }
+            buf.push(self.in_stream.next()?);
+        }
+    }
+
+    /// skips whitespace and comments, returns the first char after them and the comments
+    fn skip_trivia(&mut self) -> Result<(char, Vec<Trivia>), Box<Diagnostic>>{
+        let mut trivia = vec![];
+        loop {
+            let ch = self.in_stream.next()?;
+            if ch == ' ' || ch == '\n' || ch == '\t' || ch == '\r' {
+                continue;
+            }
+            let start = self.in_stream.mark();
+            let (kind, text) = if ch == '#' || (ch == '/' && self.in_stream.peek() == '/') {
+                (TriviaKind::LINE, self.read_until_f(|ch| ch != '\n' && ch != '\r', ch)?)
+            }
+            else if ch == '/' && self.in_stream.peek() == '*' {
+                (TriviaKind::BLOCK, self.read_block_comment(start)?)
+            }
+            else {
+                return Ok((ch, trivia));
+            };
+            trivia.push(Trivia { kind, text, span: start.to(self.in_stream.mark()) });
+        }
+    }
+
+    pub fn prase_next(&mut self) -> Result<Token, Box<Diagnostic>>{
+        let (ch, trivia) = self.skip_trivia()?;
+        let start = self.in_stream.mark();
         let mut ret = Token::new_eof();
+        if self.in_stream.past_end() {
+            ret.span = start;
+            ret.trivia = trivia;
+            return Ok(ret);
+        }
         if ch.is_ascii_digit() {
-            let buf = self.read_until_f(|ch| {ch.is_ascii_digit() || ch == '.' as u8}, ch);
-            ret = Token::new(TokenType::NUMBER, buf);
+            let literal = self.read_number(ch)?;
+            ret = match number_value(&literal) {
+                Ok(_) => Token::new(TokenType::NUMBER, literal),
+                Err(reason) => {
+                    let span = start.to(self.in_stream.mark());
+                    let diagnostic = Diagnostic::error("E0107", format!("malformed number {}", literal), Some(span)).with_help(reason);
+                    self.error_token(diagnostic, literal, span)
+                }
+            };
+        }
+        if ch == '\"' {
+            ret = Token::new(TokenType::STRING, self.read_string(start)?);
         }
-        if ch == '\"' as u8 { 
-            let buf = self.read_until(|ch: u8| {ch != '\"' as u8});
-            self.in_stream.next();
-            ret = Token::new(TokenType::STRING, buf);
+        if ch == 'r' && self.is_raw_string() {
+            ret = Token::new(TokenType::STRING, self.read_raw_string(start)?);
         }
-        if ch.is_ascii_alphabetic(){
-            let word = self.read_until_f(|ch: u8| {ch.is_ascii_alphabetic() || ch.is_ascii_digit() || ch == '_' as u8}, ch);
+        else if ch.is_xid_start(){
+            let word = self.read_until_f(|ch| ch.is_xid_continue(), ch)?;
             if word == "NUMBER" {
                 ret = Token::new(TokenType::TYPENUMBER, word);
             }
@@ -84,9 +283,30 @@ impl TokenStream{
             else if word == "STRING" {
                 ret = Token::new(TokenType::TYPESTRING, word);
             }
+            else if word == "LIST" {
+                ret = Token::new(TokenType::TYPELIST, word);
+            }
+            else if word == "MAP" {
+                ret = Token::new(TokenType::TYPEMAP, word);
+            }
             else if word == "WHILE" {
                 ret = Token::new(TokenType::WHILE, word);
             }
+            else if word == "FOR" {
+                ret = Token::new(TokenType::FOR, word);
+            }
+            else if word == "TO" {
+                ret = Token::new(TokenType::TO, word);
+            }
+            else if word == "STEP" {
+                ret = Token::new(TokenType::STEP, word);
+            }
+            else if word == "BREAK" {
+                ret = Token::new(TokenType::BREAK, word);
+            }
+            else if word == "CONTINUE" {
+                ret = Token::new(TokenType::CONTINUE, word);
+            }
             else if word == "IF"{
                 ret = Token::new(TokenType::IF, word);
             }
@@ -106,116 +326,239 @@ impl TokenStream{
                 ret = Token::new(TokenType::VAR, word);
             }
         }
-        if ch == '{' as u8{
-            ret = Token::new(TokenType::BEGIN, String::from_utf8(vec![ch]).unwrap());
+        if ch == '{'{
+            ret = Token::new(TokenType::BEGIN, String::from(ch));
+        }
+        else if ch == '}'{
+            ret = Token::new(TokenType::END, String::from(ch));
         }
-        else if ch == '}' as u8{
-            ret = Token::new(TokenType::END, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '('{
+            ret = Token::new(TokenType::LBRAC, String::from(ch));
         }
-        else if ch == '(' as u8{
-            ret = Token::new(TokenType::LBRAC, String::from_utf8(vec![ch]).unwrap());
+        else if ch == ')'{
+            ret = Token::new(TokenType::RBRAC, String::from(ch));
         }
-        else if ch == ')' as u8{
-            ret = Token::new(TokenType::RBRAC, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '['{
+            ret = Token::new(TokenType::LSQUARE, String::from(ch));
         }
-        else if ch == '=' as u8{
-            if self.in_stream.peek() == '=' as u8{
-                self.in_stream.next();
+        else if ch == ']'{
+            ret = Token::new(TokenType::RSQUARE, String::from(ch));
+        }
+        else if ch == '='{
+            if self.in_stream.peek() == '='{
+                self.in_stream.next()?;
                 ret = Token::new(TokenType::EQUAL, String::from("=="));
             }
             else{
-                ret = Token::new(TokenType::ASSIGN, String::from_utf8(vec![ch]).unwrap());
+                ret = Token::new(TokenType::ASSIGN, String::from(ch));
             }
         }
 
-        else if ch == '+' as u8{
-            ret = Token::new(TokenType::ADD, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '+'{
+            ret = Token::new(TokenType::ADD, String::from(ch));
         }
-        else if ch == '-' as u8{
-            if self.in_stream.peek().is_ascii_digit() {
-                let buf = self.read_until_f(|ch| {ch.is_ascii_digit() || ch == '.' as u8}, ch);
-                ret = Token::new(TokenType::NUMBER, buf);
-            }
-            else{
-                ret = Token::new(TokenType::SUB, String::from_utf8(vec![ch]).unwrap());
-            }
+        //a - is always an operator, -1 is NEG applied to 1
+        else if ch == '-'{
+            ret = Token::new(TokenType::SUB, String::from(ch));
         }
-        else if ch == '*' as u8{
-            ret = Token::new(TokenType::MUL, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '*'{
+            ret = Token::new(TokenType::MUL, String::from(ch));
         }
-        else if ch == '/' as u8{
-            ret = Token::new(TokenType::DIV, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '/'{
+            ret = Token::new(TokenType::DIV, String::from(ch));
         }
-        else if ch == '%' as u8{
-            ret = Token::new(TokenType::MOD, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '%'{
+            ret = Token::new(TokenType::MOD, String::from(ch));
         }
-        else if ch == '&' as u8{
-            if self.in_stream.peek() == '&' as u8{
-                self.in_stream.next();
+        else if ch == '&'{
+            if self.in_stream.peek() == '&'{
+                self.in_stream.next()?;
                 ret = Token::new(TokenType::AND, String::from("&&"));
             }
         }
-        else if ch == '|' as u8{
-            if self.in_stream.peek() == '|' as u8{
-                self.in_stream.next();
+        else if ch == '|'{
+            if self.in_stream.peek() == '|'{
+                self.in_stream.next()?;
                 ret = Token::new(TokenType::OR, String::from("||"));
             }
             else{
-                ret = Token::new(TokenType::CAT, String::from_utf8(vec![ch]).unwrap());
+                ret = Token::new(TokenType::CAT, String::from(ch));
             }
         }
-        else if ch == ':' as u8{
-            ret = Token::new(TokenType::COLON, String::from_utf8(vec![ch]).unwrap());
+        else if ch == ':'{
+            ret = Token::new(TokenType::COLON, String::from(ch));
         }
-        else if ch == ',' as u8{
-            ret = Token::new(TokenType::COMMA, String::from_utf8(vec![ch]).unwrap());
+        else if ch == ','{
+            ret = Token::new(TokenType::COMMA, String::from(ch));
         }
-        else if ch == '!' as u8{
-            ret = Token::new(TokenType::NOT, String::from_utf8(vec![ch]).unwrap());
+        else if ch == '!'{
+            if self.in_stream.peek() == '='{
+                self.in_stream.next()?;
+                ret = Token::new(TokenType::NOTEQUAL, String::from("!="));
+            }
+            else{
+                ret = Token::new(TokenType::NOT, String::from(ch));
+            }
         }
-        else if ch == '>' as u8{
-            if self.in_stream.peek() == '=' as u8{
-                self.in_stream.next();
+        else if ch == '>'{
+            if self.in_stream.peek() == '='{
+                self.in_stream.next()?;
                 ret = Token::new(TokenType::GOE, String::from(">="));
             }
             else{
-                ret = Token::new(TokenType::GREATER, String::from_utf8(vec![ch]).unwrap());
+                ret = Token::new(TokenType::GREATER, String::from(ch));
             }
         }
-        else if ch == '<' as u8{
-            if self.in_stream.peek() == '=' as u8{
-                self.in_stream.next();
+        else if ch == '<'{
+            if self.in_stream.peek() == '='{
+                self.in_stream.next()?;
                 ret = Token::new(TokenType::LOE, String::from("<="));
             }
             else{
-                ret = Token::new(TokenType::LESS, String::from_utf8(vec![ch]).unwrap());
+                ret = Token::new(TokenType::LESS, String::from(ch));
             }
         }
-        else if ch == ';' as u8{
-            ret = Token::new(TokenType::SEMICOLON, String::from_utf8(vec![ch]).unwrap());
+        else if ch == ';'{
+            ret = Token::new(TokenType::SEMICOLON, String::from(ch));
+        }
+        else if ch == '^'{
+            ret = Token::new(TokenType::POW, String::from(ch));
         }
-        else if ch == '^' as u8{
-            ret = Token::new(TokenType::POW, String::from_utf8(vec![ch]).unwrap());
+        ret.span = start.to(self.in_stream.mark());
+        //nothing above took ch
+        if ret.token_type == TokenType::EOF {
+            ret = self.unknown_char(ch, ret.span);
         }
-        ret
+        ret.trivia = trivia;
+        Ok(ret)
     }
 
-    pub fn new(path: &str) -> TokenStream{
-        let in_stream = TextStream::new(path);
+    pub fn new(in_stream: TextStream) -> Result<TokenStream, Box<Diagnostic>>{
         let mut stream = TokenStream{
             in_stream,
             cur: Token::new_eof(),
             next: Token::new_eof(),
+            errors: vec![],
+        };
+        stream.next()?;
+        Ok(stream)
+    }
+
+    /// lexes what is left and gives back the errors of every ERROR token, a failed next ends it
+    pub fn drain_errors(&mut self) -> Vec<Diagnostic>{
+        while self.peek().token_type != TokenType::EOF {
+            if let Err(e) = self.next() {
+                self.errors.push(*e);
+            }
+        }
+        std::mem::take(&mut self.errors)
+    }
+}
+/// the value of a NUMBER token, or why the literal is not one
+/// 12, 1_000, 1.5, 2.5e-3, 0xFF, 0o17 and 0b1010 are numbers, _ only goes between digits
+pub fn number_value(literal: &str) -> Result<f64, String>{
+    let (radix, body) = match literal.get(..2) {
+        Some("0x" | "0X") => (16, &literal[2..]),
+        Some("0o" | "0O") => (8, &literal[2..]),
+        Some("0b" | "0B") => (2, &literal[2..]),
+        _ => (10, literal),
+    };
+    if radix != 10 {
+        if body.is_empty() {
+            return Err(format!("{} needs digits after it", &literal[..2]));
+        }
+        check_digits(body, radix)?;
+        return u64::from_str_radix(&body.replace('_', ""), radix).map(|val| val as f64).map_err(|_| String::from("it is too large to be a NUMBER"));
+    }
+    let (mantissa, exponent) = match body.find(['e', 'E']) {
+        Some(i) => (&body[..i], Some(&body[i + 1..])),
+        None => (body, None),
+    };
+    let mut parts = mantissa.split('.');
+    check_digits(parts.next().unwrap(), 10)?;
+    if let Some(fraction) = parts.next() {
+        if fraction.is_empty() {
+            return Err(String::from("a . needs digits after it"));
+        }
+        check_digits(fraction, 10)?;
+    }
+    if parts.next().is_some() {
+        return Err(String::from("a number has only one ."));
+    }
+    if let Some(exponent) = exponent {
+        let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if digits.is_empty() {
+            return Err(String::from("the exponent needs digits after the e"));
+        }
+        check_digits(digits, 10)?;
+    }
+    match body.replace('_', "").parse::<f64>() {
+        Ok(val) if val.is_finite() => Ok(val),
+        _ => Err(String::from("it is too large to be a NUMBER")),
+    }
+}
+
+fn check_digits(digits: &str, radix: u32) -> Result<(), String>{
+    if let Some(ch) = digits.chars().find(|ch| *ch != '_' && !ch.is_digit(radix)) {
+        let name = match radix {
+            16 => "a hex",
+            8 => "an octal",
+            2 => "a binary",
+            _ => "a decimal",
         };
-        stream.next();
-        stream
+        return Err(format!("{} is not {} digit", ch, name));
     }
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err(String::from("_ can only go between digits"));
+    }
+    Ok(())
 }
+
+/// every token in text, without the EOF at the end, or every error in it
+pub fn tokenize(text: TextStream) -> Result<Vec<Token>, Vec<Diagnostic>>{
+    let mut stream = TokenStream::new(text).map_err(|e| vec![*e])?;
+    let mut tokens = vec![];
+    loop {
+        let token = match stream.next() {
+            Ok(token) => token,
+            Err(e) => {
+                stream.errors.push(*e);
+                return Err(stream.errors);
+            }
+        };
+        if token.token_type == TokenType::EOF {
+            break;
+        }
+        tokens.push(token);
+    }
+    if stream.errors.is_empty() { Ok(tokens) } else { Err(stream.errors) }
+}
+
+/// every comment in text, in the order they appear
+pub fn comments(text: TextStream) -> Result<Vec<Trivia>, Box<Diagnostic>>{
+    let mut stream = TokenStream::new(text)?;
+    let mut trivia = vec![];
+    loop {
+        let mut token = stream.next()?;
+        trivia.append(&mut token.trivia);
+        if token.token_type == TokenType::EOF {
+            return Ok(trivia);
+        }
+    }
+}
+
 impl Stream<Token> for TokenStream{
-    fn next(&mut self) -> Token{
+    fn next(&mut self) -> Result<Token, Box<Diagnostic>>{
         self.cur = self.next.clone();
-        self.next = self.prase_next();
-        self.cur.clone()
+        match self.prase_next() {
+            Ok(token) => self.next = token,
+            Err(e) => {
+                //after an error the text is in an unknown place, so nothing more is read from it
+                self.next = Token::new_eof();
+                return Err(e);
+            }
+        }
+        Ok(self.cur.clone())
     }
     fn current(&self) -> Token {
         self.cur.clone()
@@ -223,13 +566,15 @@ impl Stream<Token> for TokenStream{
     fn peek(&self) -> Token {
         self.next.clone()
     }
-    fn reset(&mut self) {
-        self.in_stream.reset();
+    fn reset(&mut self) -> Result<(), Box<Diagnostic>> {
+        self.in_stream.reset()?;
         self.cur = Token::new_eof();
         self.next = Token::new_eof();
+        Ok(())
     }
-    fn err(&self, msg: &str) {
-        self.in_stream.err(msg);
+    /// points at cur, the token the caller has just consumed
+    fn err(&self, code: &'static str, msg: &str) -> Diagnostic {
+        Diagnostic::error(code, msg.to_string(), Some(self.cur.span))
     }
     fn eof(&self) -> bool {
         self.in_stream.eof()
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use crate::{ast::{BinaryOp, Builtin, BuiltinCall, Call, Expr, Stmt}, diagnostic::Diagnostic, util::{BaseType, Prelude, Span}};
+
+/// walks the whole program before it runs and collects every type error in it
+/// types are Option<BaseType> here, None means the error was already reported and should not cascade
+pub struct TypeChecker {
+    //one map per open block, innermost last, name -> (type, where it is declared)
+    var_types: Vec<HashMap<String, (BaseType, Option<Span>)>>,
+    //name -> (param types, return type, where it is declared)
+    func_types: HashMap<String, (Vec<BaseType>, BaseType, Option<Span>)>,
+    //return type of the function being checked, None at the top level
+    ret_type: Option<BaseType>,
+    errors: Vec<Diagnostic>
+}
+
+impl TypeChecker {
+    /// the prelude is declared before the first line, without a span
+    pub fn new(prelude: &Prelude) -> TypeChecker {
+        let globals = prelude.vars.iter().map(|(name, var_type)| (name.clone(), (var_type.clone(), None))).collect();
+        let funcs = prelude.funcs.iter().map(|(name, (params, ret))| (name.clone(), (params.clone(), ret.clone(), None))).collect();
+        TypeChecker { var_types: vec![globals], func_types: funcs, ret_type: None, errors: vec![] }
+    }
+
+    pub fn check(mut self, stmts: &[Stmt]) -> Vec<Diagnostic> {
+        for each in stmts {
+            self.check_stmt(each);
+        }
+        self.errors
+    }
+
+    /// the type of a lone expression, or every error in it
+    pub fn check_expr(mut self, expr: &Expr) -> Result<BaseType, Vec<Diagnostic>> {
+        match self.get_type(expr) {
+            Some(val_type) if self.errors.is_empty() => Ok(val_type),
+            _ => Err(self.errors),
+        }
+    }
+
+    /// records a mismatch unless found is unknown or already what was expected
+    fn expect(&mut self, expected: &BaseType, found: Option<BaseType>, span: Span, what: String) -> Option<Diagnostic> {
+        match found {
+            Some(found) if !expected.fits(&found) => {
+                Some(Diagnostic::error("E0301", format!("{} should be {:?} but is {:?}", what, expected, found), Some(span)))
+            }
+            _ => None,
+        }
+    }
+
+    fn lookup_var(&self, name: &str) -> Option<(BaseType, Option<Span>)> {
+        self.var_types.iter().rev().find_map(|scope| scope.get(name)).cloned()
+    }
+
+    fn declare_var(&mut self, name: &str, var_type: BaseType, span: Span) {
+        self.var_types.last_mut().unwrap().insert(name.to_string(), (var_type, Some(span)));
+    }
+
+    fn report(&mut self, diagnostic: Option<Diagnostic>) {
+        if let Some(diagnostic) = diagnostic {
+            self.errors.push(diagnostic);
+        }
+    }
+
+    /// checks the operand of an operator, false if it is unknown or the wrong type
+    fn check_operand(&mut self, op: String, operand_type: &BaseType, operand: &Expr, what: &str) -> bool {
+        match self.get_type(operand) {
+            Some(found) if found != *operand_type => {
+                self.errors.push(Diagnostic::error("E0302", format!("`{}` needs {:?} operands but {} is {:?}", op, operand_type, what, found), Some(operand.span())));
+                false
+            }
+            found => found.is_some(),
+        }
+    }
+
+    /// for the operators that take NUMBER or STRING, the right side has to match the left
+    fn check_same_operands(&mut self, op: BinaryOp, l: &Expr, r: &Expr) -> bool {
+        let (l_type, r_type) = match (self.get_type(l), self.get_type(r)) {
+            (Some(l_type), Some(r_type)) => (l_type, r_type),
+            _ => return false,
+        };
+        let comparable = |val_type: &BaseType| matches!(val_type, BaseType::NUMBER | BaseType::STRING);
+        if !comparable(&l_type) || !comparable(&r_type) {
+            let (what, operand, found) = if !comparable(&l_type) { ("the left side", l, l_type) } else { ("the right side", r, r_type) };
+            self.errors.push(Diagnostic::error("E0302", format!("`{}` needs NUMBER or STRING operands but {} is {:?}", op, what, found), Some(operand.span())));
+            return false;
+        }
+        if l_type != r_type {
+            self.errors.push(Diagnostic::error("E0302", format!("`{}` needs both sides to have the same type but the right side is {:?}", op, r_type), Some(r.span()))
+                .with_note(format!("the left side is {:?}", l_type), Some(l.span())));
+            return false;
+        }
+        true
+    }
+
+    fn get_type(&mut self, expr: &Expr) -> Option<BaseType> {
+        match expr {
+            Expr::NUMBER { .. } => Some(BaseType::NUMBER),
+            Expr::STRING { .. } => Some(BaseType::STRING),
+            Expr::VAR { name, span, .. } => {
+                match self.lookup_var(name) {
+                    Some((val_type, _)) => Some(val_type),
+                    None => {
+                        self.errors.push(Diagnostic::error("E0202", format!("variable {} is not declared", name), Some(*span)));
+                        None
+                    }
+                }
+            }
+            Expr::CALL(call) => {
+                self.check_call(call)
+            }
+            Expr::BUILTIN(call) => {
+                self.check_builtin(call)
+            }
+            Expr::LIST { items, .. } => {
+                let items: Vec<&Expr> = items.iter().collect();
+                self.check_items(&items, "list item").map(|item| BaseType::LIST(Box::new(item)))
+            }
+            Expr::MAP { entries, .. } => {
+                self.check_map(entries)
+            }
+            //a list takes a NUMBER index and a map a STRING key
+            Expr::INDEX { list, index, .. } => {
+                let (index_type, item) = match self.get_type(list) {
+                    Some(BaseType::LIST(item)) => (BaseType::NUMBER, *item),
+                    Some(BaseType::MAP(item)) => (BaseType::STRING, *item),
+                    Some(found) => {
+                        self.errors.push(Diagnostic::error("E0305", format!("only a LIST or MAP can be indexed but this is {:?}", found), Some(list.span())));
+                        return None;
+                    }
+                    None => return None,
+                };
+                let found = self.get_type(index);
+                let what = if index_type == BaseType::NUMBER { "the index" } else { "the key" };
+                let mismatch = self.expect(&index_type, found, index.span(), String::from(what));
+                self.report(mismatch);
+                Some(item)
+            }
+            Expr::UNARY { op, operand, .. } => {
+                //a prefix operator only has the one side, calling it left or right would be confusing
+                if self.check_operand(op.to_string(), &BaseType::NUMBER, operand, "the operand") {
+                    Some(BaseType::NUMBER)
+                }
+                else {
+                    None
+                }
+            }
+            Expr::BINARY { op, l, r, .. } => {
+                let known = match op.operand_type() {
+                    Some(operand_type) => {
+                        let l_known = self.check_operand(op.to_string(), &operand_type, l, "the left side");
+                        let r_known = self.check_operand(op.to_string(), &operand_type, r, "the right side");
+                        l_known && r_known
+                    }
+                    None => self.check_same_operands(*op, l, r),
+                };
+                if known {
+                    Some(op.result_type())
+                }
+                else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn check_call(&mut self, call: &Call) -> Option<BaseType> {
+        let (params, ret, signature) = match self.func_types.get(&call.name) {
+            Some(sig) => sig.clone(),
+            None => {
+                self.errors.push(Diagnostic::error("E0203", format!("CALL of an undeclared function {}", call.name), Some(call.span)));
+                return None;
+            }
+        };
+        for (i, (param, arg)) in params.iter().zip(&call.args).enumerate() {
+            let found = self.get_type(arg);
+            let mismatch = self.expect(param, found, arg.span(), format!("argument {} of {}", i + 1, call.name))
+                .map(|diagnostic| diagnostic.with_note(format!("{} is declared here", call.name), signature));
+            self.report(mismatch);
+        }
+        Some(ret)
+    }
+
+    /// the type every one of items fits, VOID when there are none, None once one of them is wrong
+    /// what names them in errors, like list item
+    fn check_items(&mut self, items: &[&Expr], what: &str) -> Option<BaseType> {
+        //the most specific type so far and the item it comes from
+        let mut item_type: Option<(BaseType, Span)> = None;
+        let mut known = true;
+        for (i, item) in items.iter().enumerate() {
+            match (self.get_type(item), &item_type) {
+                (Some(BaseType::VOID), _) => {
+                    self.errors.push(Diagnostic::error("E0304", format!("a {} needs a value but this returns nothing", what), Some(item.span())));
+                    known = false;
+                }
+                (Some(found), None) => item_type = Some((found, item.span())),
+                (Some(found), Some((expected, _))) if expected.fits(&found) => {}
+                (Some(found), Some((expected, _))) if found.fits(expected) => item_type = Some((found, item.span())),
+                (Some(found), Some((expected, span))) => {
+                    let diagnostic = Diagnostic::error("E0301", format!("{} {} should be {:?} but is {:?}", what, i + 1, expected, found), Some(item.span()))
+                        .with_note(format!("this {} is {:?}", what, expected), Some(*span));
+                    self.errors.push(diagnostic);
+                    known = false;
+                }
+                (None, _) => known = false,
+            }
+        }
+        if known { Some(item_type.map(|(item_type, _)| item_type).unwrap_or(BaseType::VOID)) } else { None }
+    }
+
+    /// the keys have to be STRING and the values fit one type, {} alone is a MAP<STRING, VOID>
+    fn check_map(&mut self, entries: &[(Expr, Expr)]) -> Option<BaseType> {
+        for (key, _) in entries {
+            let found = self.get_type(key);
+            let mismatch = self.expect(&BaseType::STRING, found, key.span(), String::from("a map key"));
+            self.report(mismatch);
+        }
+        let values: Vec<&Expr> = entries.iter().map(|(_, val)| val).collect();
+        self.check_items(&values, "map value").map(|item| BaseType::MAP(Box::new(item)))
+    }
+
+    /// LEN takes a LIST, MAP or STRING, PUSH and POP a LIST, the rest a MAP, the parser made sure of the arity
+    fn check_builtin(&mut self, call: &BuiltinCall) -> Option<BaseType> {
+        let arg = &call.args[0];
+        match (call.builtin, self.get_type(arg)) {
+            (_, None) => None,
+            (Builtin::LEN, Some(BaseType::LIST(_) | BaseType::MAP(_) | BaseType::STRING)) => Some(BaseType::NUMBER),
+            (Builtin::HAS | Builtin::DELETE, Some(BaseType::MAP(_))) => {
+                let key = &call.args[1];
+                let found = self.get_type(key);
+                let mismatch = self.expect(&BaseType::STRING, found, key.span(), String::from("the key"));
+                self.report(mismatch);
+                Some(if call.builtin == Builtin::HAS { BaseType::NUMBER } else { BaseType::VOID })
+            }
+            (Builtin::KEYS, Some(BaseType::MAP(_))) => Some(BaseType::LIST(Box::new(BaseType::STRING))),
+            (Builtin::PUSH, Some(BaseType::LIST(item))) => {
+                let pushed = &call.args[1];
+                let found = self.get_type(pushed);
+                let mismatch = self.expect(&item, found, pushed.span(), String::from("the item pushed"));
+                self.report(mismatch);
+                Some(BaseType::VOID)
+            }
+            (Builtin::POP, Some(BaseType::LIST(item))) => Some(*item),
+            (builtin, Some(found)) => {
+                let wanted = match builtin {
+                    Builtin::LEN => "a LIST, MAP or STRING",
+                    Builtin::PUSH | Builtin::POP => "a LIST",
+                    _ => "a MAP",
+                };
+                self.errors.push(Diagnostic::error("E0305", format!("{:?} needs {} but the argument is {:?}", builtin, wanted, found), Some(arg.span())));
+                None
+            }
+        }
+    }
+
+    fn check_block(&mut self, stmts: &[Stmt]) {
+        self.var_types.push(HashMap::new());
+        for each in stmts {
+            self.check_stmt(each);
+        }
+        self.var_types.pop();
+    }
+
+    fn check_condition(&mut self, cond: &Expr) {
+        let found = self.get_type(cond);
+        if let Some(found) = found {
+            if found != BaseType::NUMBER {
+                self.errors.push(Diagnostic::error("E0303", format!("a condition should be NUMBER but is {:?}", found), Some(cond.span()))
+                    .with_help(String::from("0 is false and everything else is true, compare the value to get a NUMBER")));
+            }
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::DECLARE { name, val_type, value, span } => {
+                let found = self.get_type(value);
+                let mismatch = self.expect(val_type, found, value.span(), format!("the value of {}", name));
+                self.report(mismatch);
+                self.declare_var(name, val_type.clone(), *span);
+            }
+            Stmt::CHANGE { name, value, .. } => {
+                let found = self.get_type(value);
+                if let Some((declared, span)) = self.lookup_var(name) {
+                    let mismatch = self.expect(&declared, found, value.span(), format!("the value of {}", name))
+                        .map(|diagnostic| diagnostic.with_note(format!("{} is declared as {:?} here", name, declared), span));
+                    self.report(mismatch);
+                }
+            }
+            Stmt::PUT { value, .. } => {
+                if let Some(BaseType::VOID) = self.get_type(value) {
+                    self.errors.push(Diagnostic::error("E0304", String::from("PUT needs a value but this returns nothing"), Some(value.span())));
+                }
+            }
+            Stmt::IF { branches, else_block, .. } => {
+                for branch in branches {
+                    self.check_condition(&branch.cond);
+                    self.check_block(&branch.body);
+                }
+                self.check_block(else_block);
+            }
+            Stmt::WHILE { cond, body, .. } => {
+                self.check_condition(cond);
+                self.check_block(body);
+            }
+            Stmt::FOR { var, var_span, from, to, step, body, .. } => {
+                let bounds = [(Some(from), "the first value of the FOR"), (Some(to), "the TO value of the FOR"), (step.as_ref(), "the STEP of the FOR")];
+                for (value, what) in bounds {
+                    if let Some(value) = value {
+                        let found = self.get_type(value);
+                        let mismatch = self.expect(&BaseType::NUMBER, found, value.span(), String::from(what));
+                        self.report(mismatch);
+                    }
+                }
+                //the loop variable lives in a scope of its own around the body
+                self.var_types.push(HashMap::new());
+                self.declare_var(var, BaseType::NUMBER, *var_span);
+                self.check_block(body);
+                self.var_types.pop();
+            }
+            Stmt::FUNCTION(func) => {
+                let params = func.params.iter().map(|param| param.val_type.clone()).collect();
+                self.func_types.insert(func.name.clone(), (params, func.ret_type.clone(), Some(func.signature)));
+                //params live in a scope of their own around the body
+                self.var_types.push(HashMap::new());
+                for param in &func.params {
+                    self.declare_var(&param.name, param.val_type.clone(), param.span);
+                }
+                self.ret_type = Some(func.ret_type.clone());
+                self.check_block(&func.body);
+                self.ret_type = None;
+                self.var_types.pop();
+            }
+            Stmt::CALL(call) => {
+                self.check_call(call);
+            }
+            Stmt::BUILTIN(call) => {
+                self.check_builtin(call);
+            }
+            Stmt::SET { target, value, .. } => {
+                let found = self.get_type(value);
+                if let Some(item) = self.get_type(target) {
+                    let mismatch = self.expect(&item, found, value.span(), String::from("the new item"));
+                    self.report(mismatch);
+                }
+            }
+            Stmt::RET { value, .. } => {
+                if let (Some(value), Some(ret_type)) = (value, self.ret_type.clone()) {
+                    let found = self.get_type(value);
+                    let mismatch = self.expect(&ret_type, found, value.span(), String::from("the returned value"));
+                    self.report(mismatch);
+                }
+            }
+            Stmt::BREAK { .. } | Stmt::CONTINUE { .. } | Stmt::EMPTY { .. } | Stmt::EOF => {}
+        }
+    }
+}
@@ -1,66 +1,149 @@
 
+use std::{collections::{BTreeMap, HashMap}, fmt};
+
+use crate::diagnostic::Diagnostic;
+
 pub trait Stream<T>{
-    fn next(&mut self) -> T;
+    fn next(&mut self) -> Result<T, Box<Diagnostic>>;
     fn peek(&self) -> T;
     fn current(&self) -> T;
-    fn reset(&mut self);
+    fn reset(&mut self) -> Result<(), Box<Diagnostic>>;
     fn eof(&self) -> bool;
-    fn err(&self, msg: &str);
+    /// builds an error pointing at where the stream is, the caller decides to return it
+    fn err(&self, code: &'static str, msg: &str) -> Diagnostic;
 }
 
-#[derive(Debug)]
-pub enum NodeType{
-    PUT,
-    DECLARE,
-    CHANGE,
-    IF,
-    WHILE,
-    EXPRESSION,
-    VAL,
-    EOF,
-    EOL
+/// a piece of the source, byte offsets plus the row and col it starts and ends at
+/// end, end_row and end_col are exclusive, they point just past the last byte
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Span{
+    pub start: usize,
+    pub end: usize,
+    pub row: usize,
+    pub col: usize,
+    pub end_row: usize,
+    pub end_col: usize
 }
-impl NodeType {
-    pub fn is_eof(&self) -> bool{
-        match self {
-            NodeType::EOF => true,
-            _ => false,
-        }
+
+/// row:col..end_row:end_col, the offsets would only be noise in an ast dump
+impl fmt::Debug for Span{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}..{}:{}", self.row, self.col, self.end_row, self.end_col)
+    }
+}
+
+impl Span{
+    /// from the start of self to the end of other
+    pub fn to(&self, other: Span) -> Span{
+        Span { start: self.start, row: self.row, col: self.col, end: other.end, end_row: other.end_row, end_col: other.end_col }
     }
 }
-#[derive(Debug)]
+
+#[derive(Clone, PartialEq)]
 pub enum BaseType{
     NUMBER,
     STRING,
+    //LIST<NUMBER>, the type of every item
+    LIST(Box<BaseType>),
+    //MAP<STRING, NUMBER>, keys are always STRING, this is the type of every value
+    MAP(Box<BaseType>),
     VOID
 }
-impl Clone for BaseType{
-    fn clone(&self) -> BaseType {
-        match self{
-            BaseType::NUMBER => BaseType::NUMBER,
-            BaseType::STRING => BaseType::STRING,
-            BaseType::VOID => BaseType::VOID
+
+/// the way it is written in the source, so errors read LIST<NUMBER>
+impl fmt::Debug for BaseType{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BaseType::NUMBER => write!(f, "NUMBER"),
+            BaseType::STRING => write!(f, "STRING"),
+            BaseType::LIST(item) => write!(f, "LIST<{:?}>", item),
+            BaseType::MAP(item) => write!(f, "MAP<STRING, {:?}>", item),
+            BaseType::VOID => write!(f, "VOID"),
         }
     }
 }
 
-impl Clone for NodeType{
-    fn clone(&self) -> NodeType {
+impl BaseType{
+    /// found can stand where self is wanted, [] is a LIST<VOID> and fits any list, {} fits any map
+    pub fn fits(&self, found: &BaseType) -> bool {
+        match (self, found) {
+            (BaseType::LIST(_), BaseType::LIST(item)) if **item == BaseType::VOID => true,
+            (BaseType::LIST(expected), BaseType::LIST(found)) => expected.fits(found),
+            (BaseType::MAP(_), BaseType::MAP(item)) if **item == BaseType::VOID => true,
+            (BaseType::MAP(expected), BaseType::MAP(found)) => expected.fits(found),
+            _ => self == found,
+        }
+    }
+
+    /// the type of the items of a list or the values of a map, the most specific one so [[], [1]] is LIST<LIST<NUMBER>>
+    /// VOID when there is none, an item that does not fit is left to the type checker
+    pub fn of_items(types: impl IntoIterator<Item = BaseType>) -> BaseType {
+        let mut item_type = BaseType::VOID;
+        for found in types {
+            if item_type == BaseType::VOID || found.fits(&item_type) {
+                item_type = found;
+            }
+        }
+        item_type
+    }
+}
+
+/// a value a variable can hold
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value{
+    NUMBER(f64),
+    STRING(String),
+    //the type of the items, so an empty list still knows it, and the items
+    LIST(BaseType, Vec<Value>),
+    //the same for a map, kept sorted by key so it is always walked in the same order
+    MAP(BaseType, BTreeMap<String, Value>)
+}
+impl Value{
+    pub fn val_type(&self) -> BaseType{
+        match self {
+            Value::NUMBER(_) => BaseType::NUMBER,
+            Value::STRING(_) => BaseType::STRING,
+            Value::LIST(item, _) => BaseType::LIST(Box::new(item.clone())),
+            Value::MAP(item, _) => BaseType::MAP(Box::new(item.clone())),
+        }
+    }
+
+    /// strings are quoted inside a list or map so ["1"] and [1] look different
+    fn inner(&self) -> String{
+        match self {
+            Value::STRING(val) => format!("{:?}", val),
+            val => val.to_string(),
+        }
+    }
+}
+/// the way PUT prints it
+impl fmt::Display for Value{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            NodeType::EOL => NodeType::EOL,
-            NodeType::VAL => NodeType::VAL,
-            NodeType::PUT => NodeType::PUT,
-            NodeType::EXPRESSION => NodeType::EXPRESSION,
-            NodeType::DECLARE => NodeType::DECLARE,
-            NodeType::CHANGE => NodeType::CHANGE,
-            NodeType::IF => NodeType::IF,
-            NodeType::WHILE => NodeType::WHILE,
-            NodeType::EOF => NodeType::EOF
+            Value::NUMBER(val) => write!(f, "{}", val),
+            Value::STRING(val) => write!(f, "{}", val),
+            Value::LIST(_, items) => {
+                let items: Vec<String> = items.iter().map(|item| item.inner()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::MAP(_, entries) => {
+                let entries: Vec<String> = entries.iter().map(|(key, val)| format!("{:?}: {}", key, val.inner())).collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
         }
     }
 }
 
-#[derive(Debug)]
+/// names a program can use without declaring them, globals and functions set by the host or left by an earlier run
+/// they have no span, their declaration is not in the source being parsed
+#[derive(Debug, Clone, Default)]
+pub struct Prelude{
+    pub vars: HashMap<String, BaseType>,
+    //name -> (param types, return type)
+    pub funcs: HashMap<String, (Vec<BaseType>, BaseType)>
+}
+
+#[derive(Debug, PartialEq)]
 //op 8-19
 pub enum TokenType{
     ASSIGN,
@@ -70,8 +153,16 @@ pub enum TokenType{
     STRING,
     TYPENUMBER,
     TYPESTRING,
+    TYPELIST,
+    TYPEMAP,
     WHILE,
+    FOR,
+    TO,
+    STEP,
+    BREAK,
+    CONTINUE,
     EQUAL,
+    NOTEQUAL,
     ADD,
     SUB,
     MUL,
@@ -89,6 +180,9 @@ pub enum TokenType{
     END,
     LBRAC,
     RBRAC,
+    //[ and ], list literals and indexing
+    LSQUARE,
+    RSQUARE,
     IF,
     ELSE,
     PUT,
@@ -98,110 +192,38 @@ pub enum TokenType{
     FUNCTION,
     RET,
     SEMICOLON,
-    POW
+    POW,
+    //a char the lexer does not know, its diagnostic is kept by the TokenStream
+    ERROR
 }
 impl TokenType {
-    pub fn is_cat(&self) -> bool{
+    /// binding power of a binary operator, None if the token is not one
+    /// from loose to tight: || , && , comparisons , | , + - , * / % , (unary ! -) , ^
+    pub fn precedence(&self) -> Option<u8> {
         match self {
-            TokenType::CAT => {
-                true
-            }
-            _ => {
-                false
-            }
+            TokenType::OR => Some(1),
+            TokenType::AND => Some(2),
+            TokenType::EQUAL | TokenType::NOTEQUAL | TokenType::GREATER | TokenType::LESS | TokenType::GOE | TokenType::LOE => Some(3),
+            TokenType::CAT => Some(4),
+            TokenType::ADD | TokenType::SUB => Some(5),
+            TokenType::MUL | TokenType::DIV | TokenType::MOD => Some(6),
+            TokenType::POW => Some(8),
+            _ => None,
         }
     }
 
-    pub fn is_num_op(&self) -> bool{
-        match self {
-            TokenType::GOE => {
-                true
-            }
-            TokenType::LOE => {
-                true
-            }
-            TokenType::EQUAL => {
-                true
-            }
-            TokenType::ADD => {
-                true
-            }
-            TokenType::SUB => {
-                true
-            }
-            TokenType::MOD => {
-                true
-            }
-            TokenType::MUL => {
-                true
-            }
-            TokenType::DIV => {
-                true
-            }
-            TokenType::GREATER => {
-                true
-            }
-            TokenType::LESS => {
-                true
-            }
-            TokenType::AND => {
-                true
-            }
-            TokenType::OR => {
-                true
-            }
-            TokenType::NOT => {
-                true
-            }
-            TokenType::POW => {
-                true
-            }
-            _ => {
-                false
-            }
-        }
+    /// binding power of the operand of a prefix ! or -, sits between * and ^ so -2^2 is -(2^2)
+    pub fn unary_precedence() -> u8 {
+        7
+    }
+
+    pub fn is_right_assoc(&self) -> bool {
+        matches!(self, TokenType::POW)
     }
 }
 impl Copy for TokenType{}
 impl Clone for TokenType{
     fn clone(&self) -> Self{
-        match self{
-            TokenType::POW => TokenType::POW,
-            TokenType::GOE => TokenType::GOE,
-            TokenType::LOE => TokenType::LOE,
-            TokenType::SEMICOLON => TokenType::SEMICOLON,
-            TokenType::CALL => TokenType::CALL,
-            TokenType::ASSIGN => TokenType::ASSIGN,
-            TokenType::VAR => TokenType::VAR,
-            TokenType::NUMBER => TokenType::NUMBER,
-            TokenType::STRING => TokenType::STRING,
-            TokenType::TYPENUMBER => TokenType::TYPENUMBER,
-            TokenType::TYPESTRING => TokenType::TYPESTRING,
-            TokenType::WHILE => TokenType::WHILE,
-            TokenType::EQUAL => TokenType::EQUAL,
-            TokenType::ADD => TokenType::ADD,
-            TokenType::SUB => TokenType::SUB,
-            TokenType::MUL => TokenType::MUL,
-            TokenType::DIV => TokenType::DIV,
-            TokenType::MOD => TokenType::MOD,
-            TokenType::AND => TokenType::AND,
-            TokenType::OR => TokenType::OR,
-            TokenType::NOT => TokenType::NOT,
-            TokenType::GREATER => TokenType::GREATER,
-            TokenType::LESS => TokenType::LESS,
-            TokenType::CAT => TokenType::CAT,
-            TokenType::BEGIN => TokenType::BEGIN,
-            TokenType::END => TokenType::END,
-            TokenType::LBRAC => TokenType::LBRAC,
-            TokenType::RBRAC => TokenType::RBRAC,
-            TokenType::IF => TokenType::IF,
-            TokenType::ELSE => TokenType::ELSE,
-            TokenType::EOF => TokenType::EOF,
-            TokenType::PUT => TokenType::PUT,
-            TokenType::COLON => TokenType::COLON,
-            TokenType::FUNCTION => TokenType::FUNCTION,
-            TokenType::RET => TokenType::RET,
-            TokenType::COMMA => TokenType::COMMA
-        }
+        *self
     }
 }
@@ -0,0 +1,136 @@
+use std::{io::Write, process::{Command, Stdio}};
+
+/// runs the binary with args and stdin, returns the exit code, stdout and stderr
+fn cli(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_double_basic"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn help() {
+    for args in [&[][..], &["--help"], &["-h"], &["run", "-h"]] {
+        let (code, stdout, _) = cli(args, "");
+        assert_eq!(code, 0, "{:?}", args);
+        assert!(stdout.starts_with("usage: double_basic <command>"), "{:?}: {}", args, stdout);
+    }
+}
+
+#[test]
+fn usage_errors() {
+    for (args, msg) in [
+        (&["frob", "a.bb"][..], "error: unknown command frob"),
+        (&["run"], "error: there should be a file, - or -e code after the command"),
+        (&["run", "-e"], "error: -e needs the code to run after it"),
+        (&["run", "--fast"], "error: unknown option --fast"),
+        (&["run", "a.bb", "b.bb"], "error: only one program can be given"),
+    ] {
+        let (code, stdout, stderr) = cli(args, "");
+        assert_eq!(code, 2, "{:?}", args);
+        assert!(stdout.is_empty());
+        assert!(stderr.starts_with(msg), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn inputs() {
+    assert_eq!(cli(&["run", "-e", "PUT 1 + 2;"], ""), (0, String::from("3\n"), String::new()));
+    assert_eq!(cli(&["run", "-"], "PUT \"piped\";\n"), (0, String::from("piped\n"), String::new()));
+    assert_eq!(cli(&["run", "samples/sample2.bb"], ""), (0, String::from("pp2abcab123\n"), String::new()));
+    let (_, _, stderr) = cli(&["run", "-"], "PUT X;\n");
+    assert!(stderr.contains(" --> <stdin>:1:5\n"), "{}", stderr);
+    let (_, _, stderr) = cli(&["run", "-e", "PUT X;"], "");
+    assert!(stderr.contains(" --> <-e>:1:5\n"), "{}", stderr);
+}
+
+#[test]
+fn check_does_not_run() {
+    assert_eq!(cli(&["check", "-e", "PUT 1;"], ""), (0, String::new(), String::new()));
+    //only running finds a missing RET
+    assert_eq!(cli(&["check", "-e", "FUNCTION F(): NUMBER {\n    PUT 1;\n};\nPUT CALL F();\n"], ""), (0, String::new(), String::new()));
+    let (code, stdout, stderr) = cli(&["check", "samples/sample6.bb"], "");
+    assert_eq!(code, 1);
+    assert!(stdout.is_empty());
+    assert!(stderr.starts_with("error[E0202]"), "{}", stderr);
+}
+
+#[test]
+fn dumps() {
+    let (code, stdout, _) = cli(&["tokens", "-e", "A: NUMBER = 1;"], "");
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "1:1\tVAR\tA\n1:2\tCOLON\t:\n1:4\tTYPENUMBER\tNUMBER\n1:11\tASSIGN\t=\n1:13\tNUMBER\t1\n1:14\tSEMICOLON\t;\n");
+    let (code, stdout, _) = cli(&["ast", "-e", "PUT 1 + 2;"], "");
+    assert_eq!(code, 0);
+    assert!(stdout.starts_with("PUT {\n    value: BINARY {\n        op: ADD,\n"), "{}", stdout);
+    assert!(stdout.contains("span: 1:1..1:10,"), "{}", stdout);
+    //an ELSE IF chain is one IF with a branch per condition
+    let (_, stdout, _) = cli(&["ast", "-e", "IF 1 {\n} ELSE IF 2 {\n} ELSE IF 3 {\n};"], "");
+    assert_eq!(stdout.matches("Branch {").count(), 3, "{}", stdout);
+    assert_eq!(stdout.matches("IF {").count(), 1, "{}", stdout);
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(cli(&["run", "-e", "PUT X;"], "").0, 1);
+    assert_eq!(cli(&["run", "no_such_file.bb"], "").0, 1);
+    let (code, stdout, stderr) = cli(&["run", "-e", "PUT 1;\nFUNCTION F(): NUMBER {\n    PUT 2;\n};\nPUT CALL F();\n"], "");
+    assert_eq!(code, 3);
+    //what ran before the failure stays printed
+    assert_eq!(stdout, "1\n2\n");
+    assert!(stderr.starts_with("error[E0404]"), "{}", stderr);
+}
+
+#[test]
+fn repl_keeps_state() {
+    let session = "A: NUMBER = 2;\nA * 3\n\"x\" | \"y\"\nIF A > 1 {\n    PUT A;\n}\nELSE {\n    PUT 0;\n};\nFUNCTION SQ(X: NUMBER): NUMBER {\n    RET X * X;\n};\nCALL SQ(A)\n";
+    let (code, stdout, stderr) = cli(&["repl"], session);
+    assert_eq!(code, 0);
+    //the IF waited for its ELSE before running
+    assert_eq!(stdout, "6\n\"xy\"\n2\n4\n");
+    assert!(stderr.is_empty(), "{}", stderr);
+}
+
+#[test]
+fn repl_multi_line_statement() {
+    let session = "Y: NUMBER = 1 +\n2;\nPUT Y\n* 2;\nY\n";
+    let (code, stdout, stderr) = cli(&["repl"], session);
+    assert_eq!(code, 0);
+    //the statements waited for their ;, the bare Y did not
+    assert_eq!(stdout, "6\n3\n");
+    assert!(stderr.is_empty(), "{}", stderr);
+}
+
+#[test]
+fn repl_recovers_from_errors() {
+    let session = "A: NUMBER = 1;\nA = \"s\";\nPUT B;\nFUNCTION F(): NUMBER {\n    PUT 9;\n};\nCALL F()\nA = A + 1;\nA\n";
+    let (code, stdout, stderr) = cli(&["repl"], session);
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "9\n2\n");
+    let codes: Vec<&str> = stderr.lines().filter(|line| line.starts_with("error[")).map(|line| &line[6..11]).collect();
+    assert_eq!(codes, ["E0301", "E0202", "E0404"]);
+    assert!(stderr.contains(" --> <repl>:1:5\n"), "{}", stderr);
+}
+
+#[test]
+fn repl_meta_commands() {
+    let script = std::env::temp_dir().join("double_basic_repl_load.bb");
+    std::fs::write(&script, "LOADED: STRING = \"yes\";\n").unwrap();
+    let session = format!(":load {}\nN: NUMBER = 1.5;\n:vars\n:type N > 1\n:type LOADED | \"!\"\n:ast -N\n:reset\n:vars\nN\n:type\n:frob\n:quit\nPUT 1;\n", script.display());
+    let (code, stdout, stderr) = cli(&["repl"], &session);
+    assert_eq!(code, 0);
+    assert!(stdout.starts_with("LOADED: STRING = \"yes\"\nN: NUMBER = 1.5\nNUMBER\nSTRING\nUNARY {\n    op: NEG,\n"), "{}", stdout);
+    //nothing after :quit runs, and :reset left nothing for the second :vars
+    assert!(stdout.ends_with("}\n"), "{}", stdout);
+    assert!(stderr.starts_with("error[E0202]: variable N is not declared"), "{}", stderr);
+    assert!(stderr.contains("error: :type needs an argument"), "{}", stderr);
+    assert!(stderr.contains("error: unknown command :frob"), "{}", stderr);
+    assert_eq!(cli(&["repl", "a.bb"], "").0, 2);
+}
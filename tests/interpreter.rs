@@ -0,0 +1,189 @@
+use std::io::{self, Read};
+
+use double_basic::{BaseType, Interpreter, TextStream, Value};
+
+fn codes(interpreter: &mut Interpreter, source: &str) -> Vec<&'static str> {
+    interpreter.run(source).unwrap_err().diagnostics.iter().map(|diagnostic| diagnostic.code).collect()
+}
+
+#[test]
+fn reads_back_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.run("A: NUMBER = 1 + 2;\nS: STRING = \"a\" | \"b\";\nIF A {\n    L: NUMBER = 5;\n    A = L;\n};\n").unwrap();
+    assert_eq!(interpreter.get("A"), Some(Value::NUMBER(5.0)));
+    assert_eq!(interpreter.get("S"), Some(Value::STRING(String::from("ab"))));
+    //locals are gone with their block
+    assert_eq!(interpreter.get("L"), None);
+}
+
+#[test]
+fn preloaded_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set("NAME", Value::STRING(String::from("basic")));
+    interpreter.set("N", Value::NUMBER(2.0));
+    interpreter.run("GREETING: STRING = \"hi \" | NAME;\nN = N * 10;\n").unwrap();
+    assert_eq!(interpreter.get("GREETING"), Some(Value::STRING(String::from("hi basic"))));
+    assert_eq!(interpreter.get("N"), Some(Value::NUMBER(20.0)));
+    //preloaded globals are type checked like declared ones
+    assert_eq!(codes(&mut interpreter, "PUT NAME + 1;\n"), ["E0302"]);
+    assert_eq!(codes(&mut interpreter, "N: NUMBER = 1;\n"), ["E0209"]);
+    //setting the other type replaces it
+    interpreter.set("N", Value::STRING(String::from("x")));
+    assert_eq!(interpreter.get("N"), Some(Value::STRING(String::from("x"))));
+    assert_eq!(codes(&mut interpreter, "N = 1;\n"), ["E0301"]);
+}
+
+#[test]
+fn list_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set("XS", Value::LIST(BaseType::NUMBER, vec![Value::NUMBER(1.0)]));
+    interpreter.run("PUSH(XS, 2);\nNAMES: LIST<STRING> = [];\n").unwrap();
+    assert_eq!(interpreter.get("XS"), Some(Value::LIST(BaseType::NUMBER, vec![Value::NUMBER(1.0), Value::NUMBER(2.0)])));
+    //an empty list still knows the type of its items
+    assert_eq!(interpreter.get("NAMES"), Some(Value::LIST(BaseType::STRING, vec![])));
+    assert_eq!(codes(&mut interpreter, "PUSH(NAMES, 1);\n"), ["E0301"]);
+    assert_eq!(interpreter.eval("XS[1] * 10").unwrap(), Some(Value::NUMBER(20.0)));
+    assert_eq!(interpreter.eval("[\"a\"]").unwrap().unwrap().to_string(), "[\"a\"]");
+}
+
+#[test]
+fn map_globals() {
+    let mut interpreter = Interpreter::new();
+    let entries = [(String::from("b"), Value::NUMBER(2.0)), (String::from("a"), Value::NUMBER(1.0))];
+    interpreter.set("M", Value::MAP(BaseType::NUMBER, entries.into_iter().collect()));
+    interpreter.run("M[\"c\"] = M[\"a\"] + M[\"b\"];\nK: LIST<STRING> = KEYS(M);\n").unwrap();
+    //keys come out sorted, whatever order they went in
+    let keys = ["a", "b", "c"].iter().map(|key| Value::STRING(key.to_string())).collect();
+    assert_eq!(interpreter.get("K"), Some(Value::LIST(BaseType::STRING, keys)));
+    assert_eq!(interpreter.eval("M").unwrap().unwrap().to_string(), "{\"a\": 1, \"b\": 2, \"c\": 3}");
+    assert_eq!(codes(&mut interpreter, "M[\"d\"] = \"x\";\n"), ["E0301"]);
+}
+
+#[test]
+fn state_carries_over() {
+    let mut interpreter = Interpreter::new();
+    interpreter.run("TOTAL: NUMBER = 0;\nFUNCTION ADD(X: NUMBER) {\n    TOTAL = TOTAL + X;\n};\n").unwrap();
+    interpreter.run("CALL ADD(3);\n").unwrap();
+    interpreter.run("CALL ADD(4);\n").unwrap();
+    assert_eq!(interpreter.get("TOTAL"), Some(Value::NUMBER(7.0)));
+    //the declaration is in an earlier source, so there is no note pointing at it
+    let error = interpreter.run("CALL ADD(1, 2);\n").unwrap_err();
+    assert_eq!(error.diagnostics[0].code, "E0204");
+    assert!(error.diagnostics[0].children.is_empty());
+    //nor can an earlier run's function be declared again
+    assert_eq!(codes(&mut interpreter, "FUNCTION ADD() {\n    PUT 1;\n};\n"), ["E0212"]);
+}
+
+#[test]
+fn structured_errors() {
+    let mut interpreter = Interpreter::new();
+    let error = interpreter.run("A: NUMBER = 1;\nPUT B;\n").unwrap_err();
+    assert_eq!(error.name, "<input>");
+    assert_eq!(error.diagnostics.len(), 1);
+    let span = error.diagnostics[0].span.unwrap();
+    assert_eq!((span.row, span.col), (2, 5));
+    assert!(error.render(false).starts_with("error[E0202]: variable B is not declared\n --> <input>:2:5\n"), "{}", error);
+    //nothing ran, so nothing was declared
+    assert_eq!(interpreter.get("A"), None);
+    //a runtime error keeps what ran before it
+    let error = interpreter.run("A: NUMBER = 1;\nFUNCTION F(): NUMBER {\n    PUT 1;\n};\nPUT CALL F();\n").unwrap_err();
+    assert_eq!(error.diagnostics[0].code, "E0404");
+    assert_eq!(interpreter.get("A"), Some(Value::NUMBER(1.0)));
+    let error = interpreter.run_file("./samples/no_such_sample.bb").unwrap_err();
+    assert_eq!(error.diagnostics[0].code, "E0001");
+}
+
+#[test]
+fn any_source() {
+    let mut interpreter = Interpreter::new();
+    interpreter.run_text(TextStream::from(String::from("A: NUMBER = 1;\n")), "string").unwrap();
+    interpreter.run_reader("B: NUMBER = A + 1;\n".as_bytes(), "reader").unwrap();
+    interpreter.run_text(TextStream::from_bytes(b"C: STRING = \"c\";\n".to_vec()), "bytes").unwrap();
+    assert_eq!(interpreter.get("B"), Some(Value::NUMBER(2.0)));
+    assert_eq!(interpreter.get("C"), Some(Value::STRING(String::from("c"))));
+    let error = interpreter.run_reader("PUT D;\n".as_bytes(), "reader").unwrap_err();
+    assert!(error.render(false).contains(" --> reader:1:5\n"), "{}", error);
+}
+
+#[test]
+fn unreadable_source() {
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("connection reset"))
+        }
+    }
+    let error = Interpreter::new().run_reader(Broken, "socket").unwrap_err();
+    assert_eq!(error.diagnostics[0].code, "E0001");
+    assert_eq!(error.diagnostics[0].message, "cannot read socket: connection reset");
+}
+
+#[test]
+fn empty_programs() {
+    let mut interpreter = Interpreter::new();
+    for source in ["", "\n", "  \n\t\n", ";", ";;\n;"] {
+        assert!(interpreter.run(source).is_ok(), "{:?}", source);
+    }
+    assert!(Interpreter::new().run_reader(io::empty(), "empty").is_ok());
+}
+
+#[test]
+fn expressions() {
+    let mut interpreter = Interpreter::new();
+    interpreter.run("N: NUMBER = 4;\nFUNCTION HI() {\n    PUT \"hi\";\n};\n").unwrap();
+    assert_eq!(interpreter.eval("N * 10 + 2").unwrap(), Some(Value::NUMBER(42.0)));
+    assert_eq!(interpreter.eval("\"n\" | \"m\";").unwrap(), Some(Value::STRING(String::from("nm"))));
+    assert_eq!(interpreter.eval("CALL HI()").unwrap(), None);
+    assert_eq!(interpreter.eval("N +").unwrap_err().diagnostics[0].code, "E0201");
+    assert_eq!(interpreter.eval("N N").unwrap_err().diagnostics[0].code, "E0201");
+    assert_eq!(interpreter.eval("N | \"x\"").unwrap_err().diagnostics[0].code, "E0302");
+    assert_eq!(interpreter.globals(), [(String::from("N"), Value::NUMBER(4.0))]);
+}
+
+#[test]
+fn source_ends_mid_word() {
+    //the last token runs into the end of the source
+    let mut interpreter = Interpreter::new();
+    interpreter.run("NUMBER_12: NUMBER = 12;\nCOPY: NUMBER = NUMBER_12").unwrap();
+    assert_eq!(interpreter.get("COPY"), Some(Value::NUMBER(12.0)));
+}
+
+#[test]
+fn unicode_programs() {
+    let mut interpreter = Interpreter::new();
+    interpreter.run("名前: STRING = \"山田\" | \"さん\";\n").unwrap();
+    assert_eq!(interpreter.get("名前"), Some(Value::STRING(String::from("山田さん"))));
+    //the carets are as wide as the chars above them
+    let error = interpreter.run("PUT 名前 | 未定義;\n").unwrap_err();
+    assert!(error.render(false).contains(" --> <input>:1:10\n  |\n1 | PUT 名前 | 未定義;\n  |            ^^^^^^\n"), "{}", error);
+}
+
+#[test]
+fn every_unknown_char_is_reported() {
+    let mut interpreter = Interpreter::new();
+    let error = interpreter.run("PUT 1 @ 2;\nA: NUMBER = $;\nPUT B;\nPUT 1 ~;\n").unwrap_err();
+    let found: Vec<(&str, usize)> = error.diagnostics.iter().map(|diagnostic| (diagnostic.code, diagnostic.span.unwrap().row)).collect();
+    //the parser stops at the first one, the lexer still finds the rest
+    assert_eq!(found, [("E0106", 1), ("E0106", 2), ("E0106", 4)]);
+    //a parse error before the first one is kept
+    let error = interpreter.run("PUT 1 +;\nPUT @;\n").unwrap_err();
+    let codes: Vec<&str> = error.diagnostics.iter().map(|diagnostic| diagnostic.code).collect();
+    assert_eq!(codes, ["E0201", "E0106"]);
+    assert_eq!(interpreter.eval("1 # 2").unwrap(), Some(Value::NUMBER(1.0)));
+    assert_eq!(interpreter.eval("1 ? 2").unwrap_err().diagnostics[0].code, "E0106");
+}
+
+#[test]
+fn deep_nesting() {
+    //runs on a test thread, with a far smaller stack than the binary gives
+    let mut interpreter = Interpreter::new();
+    let brackets = |n: usize| format!("PUT {}1{};\n", "(".repeat(n), ")".repeat(n));
+    interpreter.run(&brackets(120)).unwrap();
+    assert_eq!(codes(&mut interpreter, &brackets(1000)), ["E0201"]);
+    let blocks = |n: usize| format!("{}PUT 1;\n{}", "IF 1 {\n".repeat(n), "};\n".repeat(n));
+    interpreter.run(&blocks(120)).unwrap();
+    assert_eq!(codes(&mut interpreter, &blocks(1000)), ["E0201"]);
+    //A + B + C nests to the left without any brackets
+    let sum = format!("PUT {};\n", vec!["1"; 1000].join(" + "));
+    assert_eq!(codes(&mut interpreter, &sum), ["E0201"]);
+}
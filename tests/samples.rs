@@ -0,0 +1,281 @@
+use std::{env, fs, process::Command};
+
+/// runs the binary with args, returns whether it exited cleanly, what it printed and what it complained about
+fn run(args: &[&str]) -> (bool, Vec<String>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_double_basic"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.success(), stdout.lines().map(String::from).collect(), String::from_utf8(output.stderr).unwrap())
+}
+
+/// runs samples/sample{n}.bb
+fn run_sample(n: u32) -> (bool, Vec<String>, String) {
+    run(&["run", &format!("./samples/sample{}.bb", n)])
+}
+
+/// runs a script that only exists in the test
+fn run_source(name: &str, source: &str) -> (bool, Vec<String>, String) {
+    let path = env::temp_dir().join(format!("double_basic_{}.bb", name));
+    fs::write(&path, source).unwrap();
+    run(&["run", path.to_str().unwrap()])
+}
+
+fn assert_sample(n: u32, expected: &[&str]) {
+    let (ok, lines, stderr) = run_sample(n);
+    assert!(ok, "sample{} failed: {}", n, stderr);
+    assert_eq!(lines, expected, "sample{}", n);
+}
+
+fn assert_error(name: &str, source: &str, code: &str) {
+    let (ok, _, stderr) = run_source(name, source);
+    assert!(!ok, "{} should fail", name);
+    assert!(stderr.starts_with(&format!("error[{}]", code)), "{}: {}", name, stderr);
+    assert!(!stderr.contains("panicked"), "{}: {}", name, stderr);
+}
+
+#[test]
+fn sample1() {
+    assert_sample(1, &["ABCDEFGABCDEFGH", "A is less than B", "4", "3", "2", "1", "0"]);
+}
+
+#[test]
+fn sample2() {
+    assert_sample(2, &["pp2abcab123"]);
+}
+
+#[test]
+fn sample3() {
+    assert_sample(3, &[]);
+}
+
+#[test]
+fn sample4() {
+    assert_sample(4, &[]);
+}
+
+#[test]
+fn sample5() {
+    assert_sample(5, &[]);
+}
+
+#[test]
+fn sample6() {
+    //C only lives inside the IF, so nothing runs
+    let (ok, lines, stderr) = run_sample(6);
+    assert!(!ok);
+    assert!(lines.is_empty(), "{:?}", lines);
+    assert!(stderr.starts_with("error[E0202]: variable C is not declared\n --> ./samples/sample6.bb:9:5\n"), "{}", stderr);
+    assert!(stderr.contains("note: C is declared here, but only lives until the end of that block\n --> ./samples/sample6.bb:5:5\n"), "{}", stderr);
+}
+
+#[test]
+fn sample7() {
+    assert_sample(7, &["0"]);
+}
+
+#[test]
+fn sample8() {
+    assert_sample(8, &["ABC", "0", "1", "15.588457268119896", "HELLO WORLD", "hello world", "3125"]);
+}
+
+#[test]
+fn sample9() {
+    assert_sample(9, &["1", "IF1"]);
+}
+
+#[test]
+fn sample10() {
+    assert_sample(10, &["-10", "-9", "-8", "-7", "-6", "-5", "-4", "-3", "-2", "-1"]);
+}
+
+#[test]
+fn sample11_precedence() {
+    assert_sample(11, &["5", "2", "1", "6", "9", "512", "-4", "2", "1", "1", "ABC"]);
+}
+
+#[test]
+fn sample12_functions() {
+    assert_sample(12, &["55", "Hello, double basic", "Hello, double basic", "100", "42"]);
+}
+
+#[test]
+fn sample13_comparisons() {
+    //strings order by their chars, so every upper case letter comes before the lower case ones
+    assert_sample(13, &["0", "1", "0", "1", "0", "named", "1", "1", "1", "1", "1", "1"]);
+    assert_error("mixed_not_equal", "PUT 1 != \"1\";\n", "E0302");
+    assert_error("mixed_less", "S: STRING = \"1\";\nPUT S < 2;\n", "E0302");
+    assert_error("string_math", "PUT \"a\" + \"b\";\n", "E0302");
+    assert_error("void_not_equal", "FUNCTION G() {\n    PUT 1;\n};\nPUT CALL G() != 1;\n", "E0302");
+}
+
+#[test]
+fn sample14_else_if() {
+    assert_sample(14, &["A", "B", "C", "F", "three"]);
+    assert_error("else_if_condition", "IF 0 {\n    PUT 1;\n}\nELSE IF \"a\" {\n    PUT 2;\n};\n", "E0303");
+    assert_error("else_if_semicolon", "IF 0 {\n    PUT 1;\n}\nELSE IF 1 {\n    PUT 2;\n}\nPUT 3;\n", "E0201");
+}
+
+#[test]
+fn sample15_break_continue() {
+    assert_sample(15, &["1", "3", "4", "11", "21", "8"]);
+    assert_error("break_outside_loop", "IF 1 {\n    BREAK;\n};\n", "E0210");
+    assert_error("continue_in_function", "FUNCTION F() {\n    CONTINUE;\n};\nWHILE 1 {\n    CALL F();\n};\n", "E0210");
+    assert_error("unknown_label", "OUTER: WHILE 1 {\n    BREAK INNER;\n};\n", "E0211");
+    assert_error("label_after_loop", "OUTER: WHILE 0 {\n};\nWHILE 1 {\n    BREAK OUTER;\n};\n", "E0211");
+}
+
+#[test]
+fn sample16_for() {
+    assert_sample(16, &["55", "0", "4", "8", "3", "2", "1", "11", "21", "22"]);
+    assert_error("zero_step", "FOR I: NUMBER = 0 TO 3 STEP 0 {\n};\n", "E0407");
+    assert_error("nan_bound", "FOR I: NUMBER = 0 TO 0 / 0 {\n    PUT I;\n};\n", "E0407");
+    assert_error("infinite_step", "FOR I: NUMBER = 0 TO 3 STEP 1 / 0 {\n};\n", "E0407");
+    assert_error("after_for", "FOR I: NUMBER = 0 TO 3 {\n};\nPUT I;\n", "E0202");
+    assert_error("string_bound", "FOR I: NUMBER = 0 TO \"a\" {\n};\n", "E0301");
+    assert_error("string_loop_variable", "FOR I: STRING = 0 TO 3 {\n};\n", "E0201");
+    assert_error("break_after_for", "FOR I: NUMBER = 0 TO 3 {\n};\nBREAK;\n", "E0210");
+}
+
+#[test]
+fn sample17_lists() {
+    assert_sample(17, &[
+        "[2, 3, 5, 7, 11, 13, 17, 19]", "8", "19", "[1, 3, 5, 7, 11, 13, 17]",
+        "basic double", "[\"double\", \"basic\"]", "[[0, 2], [3, 4]]", "[1, 4, 9, 16]"
+    ]);
+    assert_error("index_out_of_bounds", "A: LIST<NUMBER> = [1, 2];\nPUT A[2];\n", "E0408");
+    assert_error("fractional_index", "A: LIST<NUMBER> = [1, 2];\nA[0.5] = 1;\n", "E0408");
+    assert_error("pop_empty", "A: LIST<STRING> = [];\nPUT POP(A);\n", "E0409");
+    assert_error("mixed_items", "A: LIST<NUMBER> = [1, \"a\"];\n", "E0301");
+    assert_error("push_wrong_type", "A: LIST<NUMBER> = [];\nPUSH(A, \"a\");\n", "E0301");
+    assert_error("index_a_number", "A: NUMBER = 1;\nPUT A[0];\n", "E0305");
+    assert_error("compare_lists", "A: LIST<NUMBER> = [];\nPUT A == A;\n", "E0302");
+    assert_error("push_literal", "PUSH([1], 2);\n", "E0201");
+    assert_error("len_arity", "PUT LEN();\n", "E0204");
+}
+
+/// an empty list first does not hide the type of the items or map values after it
+#[test]
+fn empty_item_first() {
+    let (ok, lines, stderr) = run_source("empty_item_first", "PUT [[], [1]][1][0];\nPUT [[], [\"a\"]][1][0] == \"a\";\n");
+    assert!(ok, "{}", stderr);
+    assert_eq!(lines, ["1", "1"]);
+    let (ok, lines, stderr) = run_source("empty_value_first", "S: STRING = \"\";\nS = {\"a\": [], \"b\": [\"x\"]}[\"b\"][0];\nPUT S;\n");
+    assert!(ok, "{}", stderr);
+    assert_eq!(lines, ["x"]);
+}
+
+#[test]
+fn sample18_maps() {
+    assert_sample(18, &[
+        "{\"level\": \"3\", \"mode\": \"safe\", \"user\": \"amy\"}", "safe", "0", "2",
+        "a", "2", "b", "3", "c", "1", "{\"even\": [2, 4], \"odd\": [1, 3, 5]}"
+    ]);
+    assert_error("missing_key", "M: MAP<STRING, NUMBER> = {\"a\": 1};\nPUT M[\"b\"];\n", "E0410");
+    assert_error("delete_missing_key", "M: MAP<STRING, NUMBER> = {};\nDELETE(M, \"a\");\n", "E0410");
+    assert_error("number_key", "M: MAP<STRING, NUMBER> = {\"a\": 1};\nPUT M[0];\n", "E0301");
+    assert_error("number_key_type", "M: MAP<NUMBER, NUMBER> = {};\n", "E0201");
+    assert_error("mixed_values", "M: MAP<STRING, NUMBER> = {\"a\": 1, \"b\": \"x\"};\n", "E0301");
+    assert_error("has_on_a_list", "L: LIST<NUMBER> = [];\nPUT HAS(L, \"a\");\n", "E0305");
+    assert_error("keys_arity", "M: MAP<STRING, NUMBER> = {};\nPUT KEYS(M, M);\n", "E0204");
+}
+
+#[test]
+fn unary_minus() {
+    let source = "A: NUMBER = 5;\nPUT A-1;\nPUT -A;\nPUT -(1 + 2);\nPUT -2 ^ 2;\nPUT 3 * -2;\nPUT 1 - -1;\nPUT --A;\n";
+    let (ok, lines, stderr) = run_source("unary_minus", source);
+    assert!(ok, "{}", stderr);
+    //^ binds tighter than the sign, like in maths
+    assert_eq!(lines, ["4", "-5", "-3", "-4", "-6", "2", "5"]);
+}
+
+#[test]
+fn missing_file() {
+    let (ok, _, stderr) = run(&["run", "./samples/no_such_sample.bb"]);
+    assert!(!ok);
+    assert!(stderr.starts_with("error[E0001]"), "{}", stderr);
+}
+
+#[test]
+fn parse_errors() {
+    assert_error("undeclared_var", "A: NUMBER = 1;\nB = 2;\n", "E0202");
+    assert_error("unclosed_block", "WHILE 1 {\n    PUT 1;\n", "E0208");
+    assert_error("params_without_comma", "FUNCTION F(A: NUMBER B: NUMBER): NUMBER {\n    RET A;\n};\n", "E0201");
+    assert_error("trailing_param_comma", "FUNCTION F(A: NUMBER,): NUMBER {\n    RET A;\n};\n", "E0201");
+    assert_error("redeclare_function", "FUNCTION F(): NUMBER {\n    RET 1;\n};\nFUNCTION G(): NUMBER {\n    RET CALL F() + 1;\n};\nFUNCTION F(): STRING {\n    RET \"a\";\n};\nPUT CALL G();\n", "E0212");
+    assert_error("deep_brackets", &format!("PUT {}1{};\n", "(".repeat(1000), ")".repeat(1000)), "E0201");
+    assert_error("undeclared_func", "PUT CALL F(1);\n", "E0203");
+    assert_error("wrong_arity", "FUNCTION F(A: NUMBER): NUMBER {\n    RET A;\n};\nPUT CALL F(1, 2);\n", "E0204");
+    assert_error("ret_outside", "RET 1;\n", "E0205");
+}
+
+#[test]
+fn runtime_errors() {
+    assert_error("no_ret", "FUNCTION F(): NUMBER {\n    PUT 1;\n};\nPUT CALL F();\n", "E0404");
+    assert_error("stack_overflow", "FUNCTION F(): NUMBER {\n    RET CALL F();\n};\nPUT CALL F();\n", "E0403");
+    //every loop, IF and bracketed operation around the CALL takes stack too
+    let nested = |n: u32| format!("FUNCTION F(N: NUMBER): NUMBER {{\n    IF N == 0 {{\n        RET 0;\n    }};\n    FOR I: NUMBER = 0 TO 0 {{\n        WHILE 1 {{\n            IF 1 {{\n                IF 1 {{\n                    RET ((((CALL F(N - 1) + 1) * 1) - 0) + 0);\n                }};\n            }};\n        }};\n    }};\n    RET 0;\n}};\nPUT CALL F({});\n", n);
+    let (ok, lines, stderr) = run_source("nested_recursion", &nested(200));
+    assert!(ok, "{}", stderr);
+    assert_eq!(lines, ["200"]);
+    assert_error("nested_stack_overflow", &nested(100_000), "E0403");
+}
+
+#[test]
+fn error_positions() {
+    let source = "A: NUMBER = 1;\nIF A == 2 {\n    B: NUMBER = 1;\n};\nWHILE A < 3 {\n    A = A + B;\n};\n";
+    let (_, _, stderr) = run_source("while_body", source);
+    assert!(stderr.contains(":6:13\n  |\n6 |     A = A + B;\n  |             ^\n"), "{}", stderr);
+    let (_, _, stderr) = run_source("change_undeclared", "A: NUMBER = 1;\n  B = \"x\";\n");
+    assert!(stderr.contains(":2:3\n  |\n2 |   B = \"x\";\n  |   ^\n  = help: declare it before using it"), "{}", stderr);
+}
+
+#[test]
+fn rendered_notes() {
+    let source = "FUNCTION ADD(A: NUMBER, B: NUMBER): NUMBER {\n    RET A + B;\n};\nPUT CALL ADD(1);\n";
+    let (_, _, stderr) = run_source("arity_note", source);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines[0], "error[E0204]: ADD takes 2 arguments but was given 1");
+    assert_eq!(lines[3..5], ["4 | PUT CALL ADD(1);", "  |     ^^^^^^^^^^^"]);
+    assert_eq!(lines[5], "note: ADD is declared here");
+    assert_eq!(lines[8..10], ["1 | FUNCTION ADD(A: NUMBER, B: NUMBER): NUMBER {", "  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^"]);
+    //piped stderr is not a terminal, so no escape codes
+    assert!(!stderr.contains('\x1b'));
+}
+
+#[test]
+fn type_errors_before_running() {
+    let source = "PUT 1;\nS: STRING = 1 + 2;\nN: NUMBER = 3;\nN = \"x\";\nPUT N + \"y\";\nIF \"a\" {\n    PUT 1;\n};\n";
+    let (ok, lines, stderr) = run_source("type_errors", source);
+    assert!(!ok);
+    //PUT 1 never ran
+    assert!(lines.is_empty(), "{:?}", lines);
+    let codes: Vec<&str> = stderr.lines().filter(|line| line.starts_with("error[")).map(|line| &line[6..11]).collect();
+    assert_eq!(codes, ["E0301", "E0301", "E0302", "E0303"]);
+    assert!(stderr.contains("note: N is declared as NUMBER here\n --> "), "{}", stderr);
+}
+
+#[test]
+fn function_type_errors() {
+    assert_error("ret_type", "FUNCTION F(B: STRING): NUMBER {\n    RET B;\n};\n", "E0301");
+    assert_error("arg_type", "FUNCTION F(A: NUMBER): NUMBER {\n    RET A;\n};\nPUT CALL F(\"a\");\n", "E0301");
+    assert_error("put_void", "FUNCTION G() {\n    PUT 1;\n};\nPUT CALL G();\n", "E0304");
+    assert_error("bare_ret_list", "FUNCTION F(): LIST<NUMBER> {\n    RET;\n};\nPUT CALL F();\n", "E0205");
+    assert_error("bare_ret_map", "FUNCTION F(): MAP<STRING, NUMBER> {\n    RET;\n};\nPUT CALL F();\n", "E0205");
+}
+
+#[test]
+fn block_scopes() {
+    let source = "A: NUMBER = 1;\nIF A {\n    A: STRING = \"inner\";\n    PUT A;\n    B: NUMBER = 2;\n    IF B {\n        B = B + 1;\n    };\n    PUT B;\n};\nPUT A;\nI: NUMBER = 0;\nWHILE I < 2 {\n    T: NUMBER = I * 10;\n    PUT T;\n    I = I + 1;\n};\n";
+    let (ok, lines, stderr) = run_source("shadowing", source);
+    assert!(ok, "{}", stderr);
+    //the inner A shadows the outer one until its block ends, assignments reach the block that declared B
+    assert_eq!(lines, ["inner", "3", "1", "0", "10"]);
+    assert_error("redeclare", "A: NUMBER = 1;\nA: NUMBER = 2;\n", "E0209");
+    assert_error("after_while", "WHILE 0 {\n    T: NUMBER = 1;\n};\nPUT T;\n", "E0202");
+    assert_error("param_outside", "FUNCTION F(X: NUMBER): NUMBER {\n    RET X;\n};\nPUT X;\n", "E0202");
+    assert_error("function_in_if", "IF 1 {\n    Y: NUMBER = 2;\n    FUNCTION F(): NUMBER {\n        RET Y;\n    };\n    PUT CALL F();\n};\n", "E0206");
+    assert_error("function_in_while", "WHILE 0 {\n    FUNCTION F() {\n        PUT 1;\n    };\n};\nCALL F();\n", "E0206");
+}
@@ -0,0 +1,198 @@
+use double_basic::{token_stream::{comments, number_value, tokenize, TokenStream, TriviaKind}, util::{Stream, TokenType}, TextStream};
+
+fn types(source: &str) -> Vec<TokenType> {
+    tokenize(TextStream::from(source)).unwrap().iter().map(|token| token.token_type).collect()
+}
+
+#[test]
+fn minus_is_an_operator() {
+    assert_eq!(types("A-1"), [TokenType::VAR, TokenType::SUB, TokenType::NUMBER]);
+    assert_eq!(types("-1"), [TokenType::SUB, TokenType::NUMBER]);
+}
+
+#[test]
+fn collection_tokens() {
+    assert_eq!(types("A: LIST<NUMBER> = [1];"), [
+        TokenType::VAR, TokenType::COLON, TokenType::TYPELIST, TokenType::LESS, TokenType::TYPENUMBER, TokenType::GREATER,
+        TokenType::ASSIGN, TokenType::LSQUARE, TokenType::NUMBER, TokenType::RSQUARE, TokenType::SEMICOLON
+    ]);
+    assert_eq!(types("MAP<STRING, NUMBER>"), [
+        TokenType::TYPEMAP, TokenType::LESS, TokenType::TYPESTRING, TokenType::COMMA, TokenType::TYPENUMBER, TokenType::GREATER
+    ]);
+}
+
+#[test]
+fn comments_are_skipped() {
+    assert_eq!(types("# all of it\nPUT 1; // the rest\n"), [TokenType::PUT, TokenType::NUMBER, TokenType::SEMICOLON]);
+    assert_eq!(types("PUT /* a /* nested */ comment */ 4 / 2;"), [TokenType::PUT, TokenType::NUMBER, TokenType::DIV, TokenType::NUMBER, TokenType::SEMICOLON]);
+    //a comment at the very end, with no newline after it
+    assert_eq!(types("PUT 1;#"), [TokenType::PUT, TokenType::NUMBER, TokenType::SEMICOLON]);
+}
+
+#[test]
+fn crlf_line_ends() {
+    assert_eq!(types("PUT 1;\r\nPUT 2; # two\r\n"), [
+        TokenType::PUT, TokenType::NUMBER, TokenType::SEMICOLON, TokenType::PUT, TokenType::NUMBER, TokenType::SEMICOLON
+    ]);
+    //the \r is not part of the comment
+    let tokens = tokenize(TextStream::from("# first\r\nPUT 1;")).unwrap();
+    assert_eq!(tokens[0].trivia[0].text, "# first");
+    assert_eq!(tokens[0].span.row, 2);
+}
+
+#[test]
+fn comments_are_kept_as_trivia() {
+    let tokens = tokenize(TextStream::from("# first\n/* second */ PUT 1;\n")).unwrap();
+    let texts: Vec<&str> = tokens[0].trivia.iter().map(|trivia| trivia.text.as_str()).collect();
+    assert_eq!(texts, ["# first", "/* second */"]);
+    assert_eq!(tokens[0].trivia[1].kind, TriviaKind::BLOCK);
+    let span = tokens[0].trivia[1].span;
+    assert_eq!((span.row, span.col, span.end_col), (2, 1, 13));
+    assert!(tokens[1].trivia.is_empty());
+    let all = comments(TextStream::from("PUT 1; // one\n/* two\n /* three */ */\n")).unwrap();
+    let kinds: Vec<TriviaKind> = all.iter().map(|trivia| trivia.kind).collect();
+    assert_eq!(kinds, [TriviaKind::LINE, TriviaKind::BLOCK]);
+    assert_eq!(all[1].text, "/* two\n /* three */ */");
+}
+
+#[test]
+fn unterminated_block_comment() {
+    let error = &tokenize(TextStream::from("PUT 1;\n  /* a /* b */\n")).unwrap_err()[0];
+    assert_eq!(error.code, "E0102");
+    let span = error.span.unwrap();
+    assert_eq!((span.row, span.col, span.end_col), (2, 3, 5));
+}
+
+fn string(source: &str) -> String {
+    let tokens = tokenize(TextStream::from(source)).unwrap();
+    assert_eq!(tokens.len(), 1, "{:?}", tokens);
+    assert_eq!(tokens[0].token_type, TokenType::STRING);
+    tokens[0].value.clone()
+}
+
+fn error(source: &str) -> (&'static str, usize, usize) {
+    let error = &tokenize(TextStream::from(source)).unwrap_err()[0];
+    let span = error.span.unwrap();
+    (error.code, span.col, span.end_col)
+}
+
+#[test]
+fn string_escapes() {
+    assert_eq!(string(r#""say \"hi\"""#), "say \"hi\"");
+    assert_eq!(string(r#""a\\b\nc\td\re""#), "a\\b\nc\td\re");
+    assert_eq!(string(r#""\u{48}\u{e9}\u{1F600}""#), "Hé😀");
+    assert_eq!(string("\"two\nlines\""), "two\nlines");
+    assert_eq!(error(r#""\q""#), ("E0104", 2, 4));
+    assert_eq!(error(r#""\u{110000}""#), ("E0105", 2, 12));
+    assert_eq!(error(r#""\u{D800}""#), ("E0105", 2, 10));
+    assert_eq!(error(r#""\u{}""#), ("E0105", 2, 6));
+    assert_eq!(error(r#""\u48""#), ("E0105", 2, 4));
+    assert_eq!(error(r#""\u{1234567}""#), ("E0105", 2, 11));
+}
+
+#[test]
+fn unterminated_strings() {
+    //the error points at the opening quote, not at the end of the file
+    assert_eq!(error("PUT \"abc;\nPUT 1;\n"), ("E0103", 5, 6));
+    assert_eq!(error("PUT \"abc\\"), ("E0103", 5, 6));
+    assert_eq!(error("PUT \"abc\\\""), ("E0103", 5, 6));
+    assert_eq!(error("PUT r#\"abc\";"), ("E0103", 5, 8));
+}
+
+#[test]
+fn raw_strings() {
+    assert_eq!(string(r#"r"\d+\.\d*""#), r"\d+\.\d*");
+    assert_eq!(string(r##"r#"a "quoted" \n"#"##), r#"a "quoted" \n"#);
+    assert_eq!(string(r###"r##"ends at "# or "##"###), r##"ends at "# or "##);
+    //r alone is still a name
+    assert_eq!(types("r = r;"), [TokenType::VAR, TokenType::ASSIGN, TokenType::VAR, TokenType::SEMICOLON]);
+    assert_eq!(types("r #comment\n"), [TokenType::VAR]);
+}
+
+#[test]
+fn unicode_source() {
+    let tokens = tokenize(TextStream::from("名前_1: STRING = \"日本\"; Äb")).unwrap();
+    assert_eq!((tokens[0].token_type, tokens[0].value.as_str()), (TokenType::VAR, "名前_1"));
+    assert_eq!((tokens[4].token_type, tokens[4].value.as_str()), (TokenType::STRING, "日本"));
+    assert_eq!((tokens[6].token_type, tokens[6].value.as_str()), (TokenType::VAR, "Äb"));
+    //cols count chars, the offsets count bytes
+    let cols: Vec<(usize, usize)> = tokens.iter().map(|token| (token.span.col, token.span.end_col)).collect();
+    assert_eq!(cols, [(1, 5), (5, 6), (7, 13), (14, 15), (16, 20), (20, 21), (22, 24)]);
+    assert_eq!((tokens[6].span.start, tokens[6].span.end), (29, 32));
+    //names start with a letter, not a digit or a mark
+    assert_eq!(error("_A"), ("E0106", 1, 2));
+}
+
+#[test]
+fn nul_is_data() {
+    assert_eq!(string("\"a\0b\""), "a\0b");
+    assert_eq!(string("r\"\0\""), "\0");
+    assert_eq!(types("/* \0 */ PUT 1;"), [TokenType::PUT, TokenType::NUMBER, TokenType::SEMICOLON]);
+}
+
+#[test]
+fn invalid_utf8() {
+    let error = &tokenize(TextStream::from_bytes(b"PUT \"ok\";\nPUT \"\xff\";".to_vec())).unwrap_err()[0];
+    assert_eq!(error.code, "E0101");
+    let span = error.span.unwrap();
+    assert_eq!((span.row, span.col, span.start), (2, 6, 15));
+}
+
+#[test]
+fn unknown_chars() {
+    //each one is an ERROR token and lexing goes on after it
+    let mut stream = TokenStream::new(TextStream::from("A @ $B;")).unwrap();
+    let mut tokens = vec![];
+    while stream.peek().token_type != TokenType::EOF {
+        tokens.push(stream.next().unwrap());
+    }
+    let found: Vec<(TokenType, &str, usize)> = tokens.iter().map(|token| (token.token_type, token.value.as_str(), token.span.col)).collect();
+    assert_eq!(found, [(TokenType::VAR, "A", 1), (TokenType::ERROR, "@", 3), (TokenType::ERROR, "$", 5), (TokenType::VAR, "B", 6), (TokenType::SEMICOLON, ";", 7)]);
+    let errors = stream.drain_errors();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(messages, ["unknown character '@'", "unknown character '$'"]);
+    //all of them come back from tokenize, invisible ones escaped
+    let errors = tokenize(TextStream::from("PUT 1 & 2;\nPUT \0;\n")).unwrap_err();
+    let found: Vec<(&str, usize, &str)> = errors.iter().map(|error| (error.code, error.span.unwrap().row, error.message.as_str())).collect();
+    assert_eq!(found, [("E0106", 1, "unknown character '&'"), ("E0106", 2, "unknown character '\\0'")]);
+}
+
+#[test]
+fn number_literals() {
+    for (literal, val) in [("12", 12.0), ("1_000_000", 1e6), ("1.5", 1.5), ("2.5e-3", 0.0025), ("1E3", 1000.0), ("6e+2", 600.0), ("0xFF", 255.0), ("0o17", 15.0), ("0b1010", 10.0), ("0xdead_beef", 3735928559.0), ("007", 7.0)] {
+        let tokens = tokenize(TextStream::from(literal)).unwrap();
+        assert_eq!((tokens.len(), tokens[0].token_type, tokens[0].value.as_str()), (1, TokenType::NUMBER, literal));
+        assert_eq!(number_value(literal), Ok(val), "{}", literal);
+    }
+    //the sign of an exponent belongs to the literal, after a hex digit e it is an operator
+    assert_eq!(types("0x1e+5"), [TokenType::NUMBER, TokenType::ADD, TokenType::NUMBER]);
+    assert_eq!(tokenize(TextStream::from("1.5.x")).unwrap_err().len(), 1);
+}
+
+#[test]
+fn malformed_numbers() {
+    for (literal, help) in [
+        ("1.2.3", "a number has only one ."),
+        ("1.", "a . needs digits after it"),
+        ("1e", "the exponent needs digits after the e"),
+        ("1e+", "the exponent needs digits after the e"),
+        ("0xFG", "G is not a hex digit"),
+        ("0b102", "2 is not a binary digit"),
+        ("0o8", "8 is not an octal digit"),
+        ("12abc", "a is not a decimal digit"),
+        ("0x", "0x needs digits after it"),
+        ("1__0", "_ can only go between digits"),
+        ("1_", "_ can only go between digits"),
+        ("0x_1", "_ can only go between digits"),
+        ("1e999", "it is too large to be a NUMBER"),
+        ("0x1_0000_0000_0000_0000", "it is too large to be a NUMBER"),
+    ] {
+        let errors = tokenize(TextStream::from(format!("PUT {};", literal))).unwrap_err();
+        assert_eq!(errors.len(), 1, "{}", literal);
+        assert_eq!(errors[0].code, "E0107");
+        assert_eq!(errors[0].message, format!("malformed number {}", literal));
+        assert_eq!(errors[0].children[0].message, help, "{}", literal);
+        let span = errors[0].span.unwrap();
+        assert_eq!((span.col, span.end_col), (5, 5 + literal.len()), "{}", literal);
+    }
+}